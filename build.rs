@@ -4,13 +4,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     tonic_build::configure()
         .build_server(true)
-        .build_client(false)
+        .build_client(true)
         .extern_path(".google.protobuf.Duration", "::prost_types::Duration")
         .file_descriptor_set_path(format!("{}/ratelimit_descriptor.bin", out_dir))
         .compile(
             &[
                 "proto/ratelimit.proto",
                 "proto/config.proto",
+                "proto/admin.proto",
+            ],
+            &["proto"],
+        )?;
+
+    // ADS client for xDS config discovery, with the server side for stand-in management
+    // servers. Kept out of the descriptor set so reflection only lists served services.
+    tonic_build::configure()
+        .build_server(true)
+        .build_client(true)
+        .compile(
+            &[
+                "proto/envoy/service/discovery/v3/ads.proto",
+                "proto/envoy/service/discovery/v3/discovery.proto",
+                "proto/envoy/config/core/v3/base.proto",
+                "proto/google/rpc/status.proto",
             ],
             &["proto"],
         )?;
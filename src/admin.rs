@@ -0,0 +1,894 @@
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fs, io,
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+use tracing::{info, warn};
+
+use crate::{
+    audit::ConfigChangeEvent,
+    cache::CounterStatus,
+    config::{
+        load_config_from_file, CompiledRateLimit, CompiledRateLimitConfig, ConfigFormat, RateLimitConfig,
+    },
+    error::RateLimitError,
+    provider::{config_files, ConfigSources},
+    proto::generated::ratelimit::admin::v3::{
+        self as pb,
+        config_admin_service_server::{ConfigAdminService, ConfigAdminServiceServer},
+    },
+    service::{GrpcRateLimitDescriptorEntry, RateLimitService},
+    settings::Settings,
+};
+
+/// Header naming who made an admin change, recorded in the audit log
+pub const ACTOR_HEADER: &str = "x-admin-actor";
+
+/// Admin API configuration
+#[derive(Clone, Default)]
+pub struct AdminConfig {
+    /// Bearer token required by the `/admin` endpoints; they are disabled when unset
+    pub token: Option<String>,
+    /// Directory configs changed through the admin API are written to: the file holding
+    /// the domain, or `<domain>.yaml` for a new one
+    pub config_dir: Option<PathBuf>,
+}
+
+impl AdminConfig {
+    /// The admin configuration given by the settings
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            token: settings.admin_token.clone(),
+            config_dir: settings.admin_config_dir.clone(),
+        }
+    }
+}
+
+/// State of the admin APIs. The HTTP and gRPC endpoints share it, so config changes
+/// made through either are applied one at a time.
+pub struct Admin {
+    service: Arc<RateLimitService>,
+    token: Option<String>,
+    config_dir: Option<PathBuf>,
+    // Providers whose domains cannot be changed here
+    sources: Option<ConfigSources>,
+    // Serializes config changes so the directory and the loaded configs agree
+    changes: Mutex<()>,
+}
+
+/// Outcome of a config change
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigChange {
+    /// The domain was created rather than replaced
+    pub created: bool,
+    /// The change was written to the config directory
+    pub persisted: bool,
+}
+
+/// Why an admin config request failed
+#[derive(Debug, thiserror::Error)]
+pub enum AdminError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("domain '{0}' is not configured")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AdminError::Invalid(_) => StatusCode::BAD_REQUEST,
+            AdminError::NotFound(_) => StatusCode::NOT_FOUND,
+            AdminError::Conflict(_) => StatusCode::CONFLICT,
+            AdminError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        error_response(self.status_code(), self.to_string())
+    }
+}
+
+impl From<AdminError> for tonic::Status {
+    fn from(error: AdminError) -> Self {
+        match error {
+            AdminError::Invalid(message) => tonic::Status::invalid_argument(message),
+            e @ AdminError::NotFound(_) => tonic::Status::not_found(e.to_string()),
+            AdminError::Conflict(message) => tonic::Status::failed_precondition(message),
+            AdminError::Internal(message) => tonic::Status::internal(message),
+        }
+    }
+}
+
+/// A compiled rule as shown by the admin endpoints
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleView {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub requests_per_unit: u32,
+    pub unit: &'static str,
+    pub unit_multiplier: u32,
+    /// Set for calendar-aligned windows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    pub shadow_mode: bool,
+    pub unlimited: bool,
+}
+
+impl From<&CompiledRateLimit> for RuleView {
+    fn from(limit: &CompiledRateLimit) -> Self {
+        Self {
+            path: limit.path.clone(),
+            name: limit.name.clone(),
+            requests_per_unit: limit.requests_per_unit,
+            unit: limit.unit.as_str(),
+            unit_multiplier: limit.unit_multiplier,
+            timezone: limit.timezone.map(|tz| tz.name().to_string()),
+            shadow_mode: limit.shadow_mode,
+            unlimited: limit.unlimited,
+        }
+    }
+}
+
+/// Rules loaded for one domain
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainView {
+    pub domain: String,
+    pub rules: Vec<RuleView>,
+}
+
+/// Descriptor given as query parameters: `domain` plus one `entry=key=value` per entry, in order
+#[derive(Debug, Clone)]
+pub struct DescriptorQuery {
+    pub domain: String,
+    pub entries: Vec<GrpcRateLimitDescriptorEntry>,
+}
+
+impl DescriptorQuery {
+    /// Build from the decoded query pairs; an entry splits on its first `=`, so values may hold `=` and `,`
+    pub fn from_params(params: Vec<(String, String)>) -> Result<Self, String> {
+        let mut domain = None;
+        let mut entries = Vec::new();
+        for (name, value) in params {
+            match name.as_str() {
+                "domain" => domain = Some(value),
+                "entry" => {
+                    let (key, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("entry '{}' must be key=value", value))?;
+                    entries.push(GrpcRateLimitDescriptorEntry {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+                other => return Err(format!("unknown query parameter '{}'", other)),
+            }
+        }
+        let domain = domain.ok_or_else(|| "missing query parameter 'domain'".to_string())?;
+        Ok(Self { domain, entries })
+    }
+
+    fn pairs(&self) -> Vec<(String, String)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.key.clone(), entry.value.clone()))
+            .collect()
+    }
+}
+
+/// Rules a descriptor resolves to; the first one is the rule reported for it
+#[derive(Debug, Clone, Serialize)]
+pub struct LookupView {
+    pub domain: String,
+    pub entries: Vec<GrpcRateLimitDescriptorEntry>,
+    pub rules: Vec<RuleView>,
+}
+
+/// Live counter of one rule for a descriptor
+#[derive(Debug, Clone, Serialize)]
+pub struct CounterView {
+    pub rule: RuleView,
+    pub key: String,
+    pub count: u64,
+    /// Seconds until the counter expires; absent when no hits were counted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+    /// Requests are rejected from the local cache without reaching Redis
+    pub local_over_limit: bool,
+}
+
+impl From<CounterStatus> for CounterView {
+    fn from(counter: CounterStatus) -> Self {
+        Self {
+            rule: RuleView::from(&counter.limit),
+            key: counter.key,
+            count: counter.count,
+            ttl_secs: counter.ttl_secs,
+            local_over_limit: counter.local_over_limit,
+        }
+    }
+}
+
+/// Counters of the rules a descriptor matches
+#[derive(Debug, Clone, Serialize)]
+pub struct CountersView {
+    pub domain: String,
+    pub entries: Vec<GrpcRateLimitDescriptorEntry>,
+    pub counters: Vec<CounterView>,
+}
+
+/// Body of `PUT /admin/counters`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetCounter {
+    pub count: u64,
+}
+
+/// Admin endpoints for inspecting the running configuration, plus the counter and
+/// config management endpoints when a token is configured
+pub fn router(service: Arc<RateLimitService>, config: &AdminConfig) -> Router {
+    Arc::new(Admin::new(service, config)).router()
+}
+
+impl Admin {
+    /// Admin state for the service, with the token and config directory from `config`
+    pub fn new(service: Arc<RateLimitService>, config: &AdminConfig) -> Self {
+        Self {
+            service,
+            token: config.token.clone(),
+            config_dir: config.config_dir.clone(),
+            sources: None,
+            changes: Mutex::new(()),
+        }
+    }
+
+    /// Refuse changes to domains supplied by config providers, except those of a
+    /// directory provider watching the config directory, which picks them up
+    pub fn with_config_sources(mut self, sources: ConfigSources) -> Self {
+        self.sources = Some(sources);
+        self
+    }
+
+    /// The HTTP admin endpoints
+    pub fn router(self: &Arc<Self>) -> Router {
+        let mut router = Router::new()
+            .route("/rlconfig", get(config_dump))
+            .route("/rlconfig/lookup", get(config_lookup));
+
+        if let Some(token) = &self.token {
+            let admin = Router::new()
+                .route(
+                    "/admin/counters",
+                    get(get_counters).put(set_counters).delete(reset_counters),
+                )
+                .route("/admin/configs", get(list_configs))
+                .route(
+                    "/admin/configs/:domain",
+                    get(get_config).put(put_config).delete(delete_config),
+                )
+                .route_layer(middleware::from_fn_with_state(
+                    Arc::<str>::from(token.as_str()),
+                    require_token,
+                ));
+            router = router.merge(admin);
+        }
+
+        router.with_state(self.clone())
+    }
+
+    /// The gRPC config admin service, when a token is configured
+    pub fn grpc_service(self: &Arc<Self>) -> Option<GrpcConfigAdminServer> {
+        let token = self.token.as_deref()?;
+        Some(ConfigAdminServiceServer::with_interceptor(
+            GrpcConfigAdmin { admin: self.clone() },
+            RequireToken(token.into()),
+        ))
+    }
+
+    /// The source of every loaded domain config, ordered by domain
+    pub async fn configs(&self) -> Vec<RateLimitConfig> {
+        let limiter = self.service.limiter().await;
+        limiter.configs().into_iter().map(|config| config.source().clone()).collect()
+    }
+
+    /// The source of one domain's config
+    pub async fn config(&self, domain: &str) -> Result<RateLimitConfig, AdminError> {
+        let limiter = self.service.limiter().await;
+        limiter
+            .get_config(domain)
+            .map(|config| config.source().clone())
+            .ok_or_else(|| AdminError::NotFound(domain.to_string()))
+    }
+
+    /// Create or replace a domain's config. It is compiled before anything changes,
+    /// then applied and persisted, and restored if it cannot be persisted.
+    pub async fn put_config(
+        &self,
+        domain: &str,
+        config: RateLimitConfig,
+        actor: &str,
+    ) -> Result<ConfigChange, AdminError> {
+        if config.domain != domain {
+            return Err(AdminError::Invalid(format!(
+                "config domain '{}' does not match '{}'",
+                config.domain, domain
+            )));
+        }
+        let compiled = CompiledRateLimitConfig::compile(config.clone())
+            .map_err(|e| AdminError::Invalid(e.to_string()))?;
+
+        let _changes = self.changes.lock().await;
+        self.check_owner(domain)?;
+        let previous = self.config(domain).await.ok();
+        let created = previous.is_none();
+        self.service
+            .add_config(compiled)
+            .await
+            .map_err(|e| AdminError::Internal(e.to_string()))?;
+        let persisted = match &self.config_dir {
+            Some(dir) => match write_config_file(dir.clone(), config.clone()).await {
+                Ok(()) => true,
+                Err(e) => {
+                    self.restore_config(domain, previous).await;
+                    return Err(e);
+                }
+            },
+            None => false,
+        };
+
+        let action = if created { "create" } else { "replace" };
+        self.record_change(action, domain, actor, Some(config), persisted).await;
+        Ok(ConfigChange { created, persisted })
+    }
+
+    /// Remove a domain's config, restoring it if the removal cannot be persisted.
+    /// Returns whether the removal was persisted.
+    pub async fn delete_config(&self, domain: &str, actor: &str) -> Result<bool, AdminError> {
+        let _changes = self.changes.lock().await;
+        let previous = self.config(domain).await?;
+        self.check_owner(domain)?;
+
+        self.service
+            .remove_config(domain)
+            .await
+            .map_err(|e| AdminError::Internal(e.to_string()))?;
+        let persisted = match &self.config_dir {
+            Some(dir) => match remove_config_file(dir.clone(), domain.to_string()).await {
+                Ok(()) => true,
+                Err(e) => {
+                    self.restore_config(domain, Some(previous)).await;
+                    return Err(e);
+                }
+            },
+            None => false,
+        };
+
+        self.record_change("delete", domain, actor, None, persisted).await;
+        Ok(persisted)
+    }
+
+    /// Fail when a provider supplies the domain, as it would undo the change
+    fn check_owner(&self, domain: &str) -> Result<(), AdminError> {
+        let Some(owner) = self.sources.as_ref().and_then(|sources| sources.owner(domain)) else {
+            return Ok(());
+        };
+        if let (Some(owner_dir), Some(config_dir)) = (&owner.config_dir, &self.config_dir) {
+            if same_dir(owner_dir, config_dir) {
+                return Ok(());
+            }
+        }
+        Err(AdminError::Conflict(format!(
+            "domain '{}' is provided by {}",
+            domain, owner.name
+        )))
+    }
+
+    /// Put back the config a domain had before a change that could not be persisted
+    async fn restore_config(&self, domain: &str, previous: Option<RateLimitConfig>) {
+        let restored = match previous {
+            Some(config) => match CompiledRateLimitConfig::compile(config) {
+                Ok(compiled) => self.service.add_config(compiled).await,
+                Err(e) => Err(e),
+            },
+            None => self.service.remove_config(domain).await,
+        };
+        if let Err(e) = restored {
+            warn!("Failed to restore config for domain {}: {}", domain, e);
+        }
+    }
+
+    /// Log a config change, and write it to the service's audit log
+    async fn record_change(
+        &self,
+        action: &'static str,
+        domain: &str,
+        actor: &str,
+        config: Option<RateLimitConfig>,
+        persisted: bool,
+    ) {
+        info!("Admin {} of config for domain {} by {} (persisted: {})", action, domain, actor, persisted);
+
+        let Some(audit) = self.service.limiter().await.audit_log().cloned() else {
+            return;
+        };
+        let event = ConfigChangeEvent {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            action,
+            domain: domain.to_string(),
+            actor: actor.to_string(),
+            config,
+            persisted,
+        };
+        // Waits for room in the audit queue rather than dropping the change
+        if let Err(e) = tokio::task::spawn_blocking(move || audit.record_config_change(&event)).await {
+            warn!("Failed to audit config change for domain {}: {}", domain, e);
+        }
+    }
+}
+
+/// Reject requests without `Authorization: Bearer <token>`
+async fn require_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+
+    if !authorized {
+        let mut response = error_response(StatusCode::UNAUTHORIZED, "missing or invalid admin token".to_string());
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        return response;
+    }
+    next.run(request).await
+}
+
+/// Compare without exiting early, so the token cannot be guessed from response times
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn config_dump(State(state): State<Arc<Admin>>) -> Json<Vec<DomainView>> {
+    let limiter = state.service.limiter().await;
+    let domains = limiter
+        .configs()
+        .into_iter()
+        .map(|config| DomainView {
+            domain: config.domain().to_string(),
+            rules: config.limits().into_iter().map(RuleView::from).collect(),
+        })
+        .collect();
+    Json(domains)
+}
+
+async fn config_lookup(
+    State(state): State<Arc<Admin>>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Response {
+    let query = match DescriptorQuery::from_params(params) {
+        Ok(query) => query,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+    };
+
+    let limiter = state.service.limiter().await;
+    let Some(config) = limiter.get_config(&query.domain) else {
+        return domain_not_found(&query.domain);
+    };
+
+    let pairs: Vec<(&str, &str)> = query
+        .entries
+        .iter()
+        .map(|entry| (entry.key.as_str(), entry.value.as_str()))
+        .collect();
+    let rules = config.find_limits(&pairs).iter().map(RuleView::from).collect();
+
+    Json(LookupView {
+        domain: query.domain,
+        entries: query.entries,
+        rules,
+    })
+    .into_response()
+}
+
+async fn get_counters(
+    State(state): State<Arc<Admin>>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Response {
+    counters_response(&state.service, params, None).await
+}
+
+async fn set_counters(
+    State(state): State<Arc<Admin>>,
+    Query(params): Query<Vec<(String, String)>>,
+    Json(body): Json<SetCounter>,
+) -> Response {
+    counters_response(&state.service, params, Some(body.count)).await
+}
+
+async fn reset_counters(
+    State(state): State<Arc<Admin>>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Response {
+    counters_response(&state.service, params, Some(0)).await
+}
+
+/// Read the descriptor's counters, first setting them to `count` when given
+async fn counters_response(
+    service: &RateLimitService,
+    params: Vec<(String, String)>,
+    count: Option<u64>,
+) -> Response {
+    let query = match DescriptorQuery::from_params(params) {
+        Ok(query) => query,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+    };
+    let pairs = query.pairs();
+
+    let limiter = service.limiter().await;
+    let result = match count {
+        Some(count) => {
+            info!("Admin set counters of {} {:?} to {}", query.domain, pairs, count);
+            limiter.set_counters(&query.domain, &pairs, count).await
+        }
+        None => limiter.counters(&query.domain, &pairs).await,
+    };
+
+    match result {
+        Ok(counters) if counters.is_empty() => error_response(
+            StatusCode::NOT_FOUND,
+            "no rate limited rule matches the descriptor".to_string(),
+        ),
+        Ok(counters) => Json(CountersView {
+            domain: query.domain,
+            entries: query.entries,
+            counters: counters.into_iter().map(CounterView::from).collect(),
+        })
+        .into_response(),
+        Err(RateLimitError::DomainNotFound(domain)) => domain_not_found(&domain),
+        Err(e @ RateLimitError::Redis(_)) => error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn list_configs(State(admin): State<Arc<Admin>>) -> Json<Vec<RateLimitConfig>> {
+    Json(admin.configs().await)
+}
+
+async fn get_config(State(admin): State<Arc<Admin>>, Path(domain): Path<String>) -> Response {
+    match admin.config(&domain).await {
+        Ok(config) => Json(config).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Create or replace a domain's config from a YAML body, or JSON or TOML when the
+/// content type says so
+async fn put_config(
+    State(admin): State<Arc<Admin>>,
+    Path(domain): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let format = if content_type.contains("json") {
+        ConfigFormat::Json
+    } else if content_type.contains("toml") {
+        ConfigFormat::Toml
+    } else {
+        ConfigFormat::Yaml
+    };
+    let config = match format.parse(&body) {
+        Ok(config) => config,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    let actor = actor(headers.get(ACTOR_HEADER).and_then(|value| value.to_str().ok()));
+    match admin.put_config(&domain, config.clone(), actor).await {
+        Ok(change) if change.created => (StatusCode::CREATED, Json(config)).into_response(),
+        Ok(_) => (StatusCode::OK, Json(config)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn delete_config(
+    State(admin): State<Arc<Admin>>,
+    Path(domain): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let actor = actor(headers.get(ACTOR_HEADER).and_then(|value| value.to_str().ok()));
+    match admin.delete_config(&domain, actor).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Who made a change, from the actor header or metadata, or `admin` without one
+fn actor(value: Option<&str>) -> &str {
+    value.filter(|actor| !actor.is_empty()).unwrap_or("admin")
+}
+
+/// Generated server of the gRPC config admin service, behind the token check
+pub type GrpcConfigAdminServer = InterceptedService<ConfigAdminServiceServer<GrpcConfigAdmin>, RequireToken>;
+
+/// gRPC config admin service, offering the `/admin/configs` operations
+pub struct GrpcConfigAdmin {
+    admin: Arc<Admin>,
+}
+
+impl GrpcConfigAdmin {
+    fn render(config: &RateLimitConfig, format: i32) -> Result<pb::Config, AdminError> {
+        let format = proto_format(format)?;
+        let content = config_format(format)
+            .serialize(config)
+            .map_err(|e| AdminError::Internal(e.to_string()))?;
+        Ok(pb::Config {
+            domain: config.domain.clone(),
+            format: format.into(),
+            content,
+        })
+    }
+}
+
+fn proto_format(format: i32) -> Result<pb::ConfigFormat, AdminError> {
+    pb::ConfigFormat::try_from(format)
+        .map_err(|_| AdminError::Invalid(format!("unknown config format {}", format)))
+}
+
+fn config_format(format: pb::ConfigFormat) -> ConfigFormat {
+    match format {
+        pb::ConfigFormat::Yaml => ConfigFormat::Yaml,
+        pb::ConfigFormat::Json => ConfigFormat::Json,
+        pb::ConfigFormat::Toml => ConfigFormat::Toml,
+    }
+}
+
+fn request_actor<T>(request: &tonic::Request<T>) -> String {
+    let value = request.metadata().get(ACTOR_HEADER).and_then(|value| value.to_str().ok());
+    actor(value).to_string()
+}
+
+#[tonic::async_trait]
+impl ConfigAdminService for GrpcConfigAdmin {
+    async fn list_configs(
+        &self,
+        request: tonic::Request<pb::ListConfigsRequest>,
+    ) -> Result<tonic::Response<pb::ListConfigsResponse>, tonic::Status> {
+        let format = request.into_inner().format;
+        let configs = self
+            .admin
+            .configs()
+            .await
+            .iter()
+            .map(|config| Self::render(config, format))
+            .collect::<Result<_, _>>()?;
+        Ok(tonic::Response::new(pb::ListConfigsResponse { configs }))
+    }
+
+    async fn get_config(
+        &self,
+        request: tonic::Request<pb::GetConfigRequest>,
+    ) -> Result<tonic::Response<pb::Config>, tonic::Status> {
+        let request = request.into_inner();
+        let config = self.admin.config(&request.domain).await?;
+        Ok(tonic::Response::new(Self::render(&config, request.format)?))
+    }
+
+    async fn put_config(
+        &self,
+        request: tonic::Request<pb::PutConfigRequest>,
+    ) -> Result<tonic::Response<pb::PutConfigResponse>, tonic::Status> {
+        let actor = request_actor(&request);
+        let Some(config) = request.into_inner().config else {
+            return Err(tonic::Status::invalid_argument("config is required"));
+        };
+        let parsed = config_format(proto_format(config.format)?)
+            .parse(&config.content)
+            .map_err(|e| AdminError::Invalid(e.to_string()))?;
+
+        let change = self.admin.put_config(&config.domain, parsed, &actor).await?;
+        Ok(tonic::Response::new(pb::PutConfigResponse {
+            created: change.created,
+            persisted: change.persisted,
+        }))
+    }
+
+    async fn delete_config(
+        &self,
+        request: tonic::Request<pb::DeleteConfigRequest>,
+    ) -> Result<tonic::Response<pb::DeleteConfigResponse>, tonic::Status> {
+        let actor = request_actor(&request);
+        let persisted = self.admin.delete_config(&request.into_inner().domain, &actor).await?;
+        Ok(tonic::Response::new(pb::DeleteConfigResponse { persisted }))
+    }
+}
+
+/// Rejects gRPC calls without `authorization: Bearer <token>` metadata
+#[derive(Clone)]
+pub struct RequireToken(Arc<str>);
+
+impl Interceptor for RequireToken {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        let authorized = request
+            .metadata()
+            .get(header::AUTHORIZATION.as_str())
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), self.0.as_bytes()));
+        if !authorized {
+            return Err(tonic::Status::unauthenticated("missing or invalid admin token"));
+        }
+        Ok(request)
+    }
+}
+
+fn same_dir(a: &FsPath, b: &FsPath) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Path of a new domain's file in the config directory; the domain must be a plain file name
+fn config_file(dir: &FsPath, domain: &str) -> Result<PathBuf, AdminError> {
+    let valid = !domain.is_empty()
+        && !domain.starts_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(AdminError::Invalid(format!(
+            "domain '{}' cannot be persisted as a file name",
+            domain
+        )));
+    }
+    Ok(dir.join(format!("{}.yaml", domain)))
+}
+
+/// The file in the config directory holding the domain, whatever its name and format,
+/// or `<domain>.yaml` when none does. Fails with a conflict when the domain is in
+/// several files or the new file's name is taken, as the directory would not load.
+fn domain_file(dir: &FsPath, domain: &str) -> Result<Option<PathBuf>, AdminError> {
+    let files = match config_files(dir) {
+        Ok(files) => files,
+        Err(RateLimitError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(AdminError::Internal(format!("failed to read {}: {}", dir.display(), e))),
+    };
+    let holding: Vec<&PathBuf> = files
+        .iter()
+        .filter(|path| load_config_from_file(path).is_ok_and(|config| config.domain == domain))
+        .collect();
+    match holding.as_slice() {
+        [] => Ok(None),
+        [path] => Ok(Some(path.to_path_buf())),
+        paths => Err(AdminError::Conflict(format!(
+            "domain '{}' is in several files: {}",
+            domain,
+            paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+/// Rewrite the domain's file in its own format, or create `<domain>.yaml`. The file is
+/// written next to its final path and renamed, so readers never see a partial file.
+async fn write_config_file(dir: PathBuf, config: RateLimitConfig) -> Result<(), AdminError> {
+    let write = move || -> Result<(), AdminError> {
+        fs::create_dir_all(&dir).map_err(|e| {
+            AdminError::Internal(format!("failed to persist config to {}: {}", dir.display(), e))
+        })?;
+        let path = match domain_file(&dir, &config.domain)? {
+            Some(path) => path,
+            None => {
+                let path = config_file(&dir, &config.domain)?;
+                if path.exists() {
+                    return Err(AdminError::Conflict(format!(
+                        "{} holds another domain",
+                        path.display()
+                    )));
+                }
+                path
+            }
+        };
+        let failed = |e: &dyn std::fmt::Display| {
+            AdminError::Internal(format!("failed to persist config to {}: {}", path.display(), e))
+        };
+        let format = ConfigFormat::from_path(&path).unwrap_or(ConfigFormat::Yaml);
+        let content = format.serialize(&config).map_err(|e| failed(&e))?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = dir.join(format!(".{}.tmp", file_name));
+        fs::write(&temp, content)
+            .and_then(|()| fs::rename(&temp, &path))
+            .map_err(|e| failed(&e))
+    };
+    match tokio::task::spawn_blocking(write).await {
+        Ok(result) => result,
+        Err(e) => Err(AdminError::Internal(format!("failed to persist config: {}", e))),
+    }
+}
+
+/// Remove the file holding the domain, if there is one
+async fn remove_config_file(dir: PathBuf, domain: String) -> Result<(), AdminError> {
+    let remove = move || -> Result<(), AdminError> {
+        let Some(path) = domain_file(&dir, &domain)? else {
+            return Ok(());
+        };
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(AdminError::Internal(format!(
+                "failed to remove {}: {}",
+                path.display(),
+                e
+            ))),
+            _ => Ok(()),
+        }
+    };
+    match tokio::task::spawn_blocking(remove).await {
+        Ok(result) => result,
+        Err(e) => Err(AdminError::Internal(format!("failed to remove config: {}", e))),
+    }
+}
+
+fn domain_not_found(domain: &str) -> Response {
+    AdminError::NotFound(domain.to_string()).into_response()
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_descriptor_query_from_params() {
+        let query = DescriptorQuery::from_params(params(&[
+            ("domain", "api"),
+            ("entry", "path=/search?q=a,b"),
+            ("entry", "user=alice"),
+        ]))
+        .unwrap();
+        assert_eq!(query.domain, "api");
+        assert_eq!(query.entries.len(), 2);
+        assert_eq!(query.entries[0].key, "path");
+        assert_eq!(query.entries[0].value, "/search?q=a,b");
+        assert_eq!(query.entries[1].value, "alice");
+
+        assert!(DescriptorQuery::from_params(params(&[("domain", "api")])).unwrap().entries.is_empty());
+        assert!(DescriptorQuery::from_params(params(&[("domain", "api"), ("entry", "user")])).is_err());
+        assert!(DescriptorQuery::from_params(params(&[("entry", "user=alice")])).is_err());
+        assert!(DescriptorQuery::from_params(params(&[("domain", "api"), ("entries", "user=alice")])).is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
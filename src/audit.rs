@@ -0,0 +1,566 @@
+use serde::Serialize;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{
+    cache::{DescriptorStatus, ResponseCode},
+    config::RateLimitConfig,
+    error::Result,
+    metrics::Metrics,
+    settings::Settings,
+    utils::Window,
+};
+
+/// Value written in place of redacted descriptor values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Audit log configuration
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// File to write events to; events go to stdout when unset
+    pub path: Option<PathBuf>,
+    /// Fraction of events written, between 0 and 1
+    pub sample_rate: f64,
+    /// Descriptor keys whose values are replaced with `[REDACTED]`
+    pub redact_keys: Vec<String>,
+    /// Size at which the file is rotated
+    pub max_file_bytes: u64,
+    /// Rotated files kept next to the active one
+    pub max_files: usize,
+    /// Events waiting to be written; further decisions are dropped and counted
+    pub queue_size: usize,
+    /// Write over-limit and shadow-mode decisions; config changes are always written
+    pub decisions: bool,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            sample_rate: 1.0,
+            redact_keys: Vec::new(),
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            queue_size: 10_000,
+            decisions: true,
+        }
+    }
+}
+
+impl AuditConfig {
+    /// The audit log configuration given by the settings. Decisions are written when
+    /// `audit_log_enabled` is set; config changes always are.
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            path: settings.audit_log_path.clone(),
+            sample_rate: settings.audit_log_sample_rate,
+            redact_keys: settings
+                .audit_log_redact_keys
+                .split(',')
+                .map(|key| key.trim().to_string())
+                .filter(|key| !key.is_empty())
+                .collect(),
+            max_file_bytes: settings.audit_log_max_bytes,
+            max_files: settings.audit_log_max_files,
+            queue_size: settings.audit_log_queue_size,
+            decisions: settings.audit_log_enabled,
+        }
+    }
+}
+
+/// A descriptor entry as written to the audit log
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub key: String,
+    pub value: String,
+}
+
+/// Window of the limit that was exceeded
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct AuditWindow {
+    pub unit: &'static str,
+    pub multiplier: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+impl From<Window> for AuditWindow {
+    fn from(window: Window) -> Self {
+        Self {
+            unit: window.unit.as_str(),
+            multiplier: window.multiplier,
+            timezone: window.timezone.map(|tz| tz.name().to_string()),
+        }
+    }
+}
+
+/// One over-limit or shadow-mode decision
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub timestamp: String,
+    /// `over_limit` or `shadow_mode`
+    pub decision: &'static str,
+    pub domain: String,
+    pub descriptor: Vec<AuditEntry>,
+    pub rule: String,
+    /// Hits counted in the window, unknown when served from the local cache
+    pub count: Option<u64>,
+    pub limit: u32,
+    pub window: AuditWindow,
+}
+
+/// A configuration change made through the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChangeEvent {
+    pub timestamp: String,
+    /// `create`, `replace` or `delete`
+    pub action: &'static str,
+    pub domain: String,
+    /// Who made the change, as given by the admin request
+    pub actor: String,
+    /// The new configuration; absent for deletions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<RateLimitConfig>,
+    /// Whether the change was written to the config directory
+    pub persisted: bool,
+}
+
+enum AuditSink {
+    Stdout,
+    File(RotatingFile),
+}
+
+impl AuditSink {
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        match self {
+            AuditSink::Stdout => io::stdout().lock().write_all(line),
+            AuditSink::File(file) => file.write_line(line),
+        }
+    }
+}
+
+enum AuditMessage {
+    Line(String),
+    /// Acknowledged once every earlier line is written
+    Flush(SyncSender<()>),
+}
+
+/// Structured JSON log of over-limit and shadow-mode decisions and of config changes.
+/// Events are queued and written by a background thread, so recording decisions
+/// never blocks on I/O.
+pub struct AuditLog {
+    config: AuditConfig,
+    queue: SyncSender<AuditMessage>,
+    seen: AtomicU64,
+    dropped: AtomicU64,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl AuditLog {
+    /// Create an audit log writing to the configured file, or stdout
+    pub fn new(config: AuditConfig) -> Result<Self> {
+        let sink = match &config.path {
+            Some(path) => AuditSink::File(RotatingFile::open(
+                path.clone(),
+                config.max_file_bytes,
+                config.max_files,
+            )?),
+            None => AuditSink::Stdout,
+        };
+
+        let (queue, messages) = mpsc::sync_channel(config.queue_size);
+        thread::Builder::new()
+            .name("audit-log".to_string())
+            .spawn(move || write_events(sink, messages))?;
+
+        Ok(Self {
+            config,
+            queue,
+            seen: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            metrics: None,
+        })
+    }
+
+    /// Count events dropped on a full queue in `ratelimit_audit_events_dropped`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Events dropped because the queue was full
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Block until every event recorded so far is written
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::sync_channel(1);
+        if self.queue.send(AuditMessage::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// Log the decision for a descriptor if it is over limit or a shadow-mode violation
+    pub fn record(&self, domain: &str, entries: &[(String, String)], rule: &str, status: &DescriptorStatus) {
+        if !self.config.decisions {
+            return;
+        }
+        let decision = match (status.code, status.shadow_mode) {
+            (ResponseCode::OverLimit, _) => "over_limit",
+            (ResponseCode::Ok, true) => "shadow_mode",
+            (ResponseCode::Ok, false) => return,
+        };
+        let Some(limit) = &status.current_limit else {
+            return;
+        };
+        if !self.sampled() {
+            return;
+        }
+
+        let event = AuditEvent {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            decision,
+            domain: domain.to_string(),
+            descriptor: entries
+                .iter()
+                .map(|(key, value)| AuditEntry {
+                    key: key.clone(),
+                    value: if self.config.redact_keys.contains(key) {
+                        REDACTED.to_string()
+                    } else {
+                        value.clone()
+                    },
+                })
+                .collect(),
+            rule: rule.to_string(),
+            count: status.count,
+            limit: limit.requests_per_unit,
+            window: limit.window.into(),
+        };
+
+        self.enqueue(&event);
+    }
+
+    /// Log a configuration change. These are never sampled or dropped: on a full
+    /// queue this blocks until there is room, so call it off the async executor.
+    pub fn record_config_change(&self, event: &ConfigChangeEvent) {
+        let Some(line) = Self::line(event) else {
+            return;
+        };
+        if self.queue.send(AuditMessage::Line(line)).is_err() {
+            tracing::warn!("Audit log writer has stopped");
+        }
+    }
+
+    /// Keep `sample_rate` of events, spread evenly over the stream
+    fn sampled(&self) -> bool {
+        let rate = self.config.sample_rate;
+        if rate >= 1.0 {
+            return true;
+        }
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * rate).floor() > (n * rate).floor()
+    }
+
+    /// An event as a line of JSON
+    fn line(event: &impl Serialize) -> Option<String> {
+        match serde_json::to_string(event) {
+            Ok(line) => Some(line + "\n"),
+            Err(e) => {
+                tracing::warn!("Failed to serialize audit event: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Queue an event for the writer thread, dropping it when the queue is full
+    fn enqueue(&self, event: &impl Serialize) {
+        let Some(line) = Self::line(event) else {
+            return;
+        };
+        match self.queue.try_send(AuditMessage::Line(line)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    tracing::warn!("Audit log queue is full, dropping events");
+                }
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_audit_event_dropped();
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => tracing::warn!("Audit log writer has stopped"),
+        }
+    }
+}
+
+/// Write queued events until the log is dropped
+fn write_events(mut sink: AuditSink, messages: Receiver<AuditMessage>) {
+    for message in messages {
+        match message {
+            AuditMessage::Line(line) => {
+                if let Err(e) = sink.write_line(line.as_bytes()) {
+                    tracing::warn!("Failed to write audit event: {}", e);
+                }
+            }
+            AuditMessage::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// Append-only file rotated by size: `audit.log` -> `audit.log.1` -> ... -> `audit.log.N`
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::{HitCounts, RateLimit},
+        utils::Unit,
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ratelimit_audit_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("audit.log")
+    }
+
+    fn over_limit_status() -> DescriptorStatus {
+        DescriptorStatus {
+            code: ResponseCode::OverLimit,
+            current_limit: Some(RateLimit {
+                requests_per_unit: 2,
+                unit: Unit::Minute,
+                name: None,
+                window: Window::new(Unit::Minute, 1),
+            }),
+            limit_remaining: 0,
+            duration_until_reset_secs: 30,
+            near_limit: false,
+            shadow_mode: false,
+            count: Some(3),
+            hits: HitCounts::over(1),
+        }
+    }
+
+    fn read_events(path: &PathBuf) -> Vec<serde_json::Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_audit_event_with_redaction() {
+        let path = temp_path("redaction");
+        let log = AuditLog::new(AuditConfig {
+            path: Some(path.clone()),
+            redact_keys: vec!["api_key".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let entries = vec![
+            ("api_key".to_string(), "secret".to_string()),
+            ("path".to_string(), "/search".to_string()),
+        ];
+        log.record("api", &entries, "api_key:path", &over_limit_status());
+
+        // Allowed requests are not logged
+        let mut allowed = over_limit_status();
+        allowed.code = ResponseCode::Ok;
+        log.record("api", &entries, "api_key:path", &allowed);
+        log.flush();
+
+        let events = read_events(&path);
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event["decision"], "over_limit");
+        assert_eq!(event["domain"], "api");
+        assert_eq!(event["descriptor"][0]["value"], REDACTED);
+        assert_eq!(event["descriptor"][1]["value"], "/search");
+        assert_eq!(event["rule"], "api_key:path");
+        assert_eq!(event["count"], 3);
+        assert_eq!(event["limit"], 2);
+        assert_eq!(event["window"]["unit"], "minute");
+    }
+
+    #[test]
+    fn test_audit_sampling() {
+        let path = temp_path("sampling");
+        let log = AuditLog::new(AuditConfig {
+            path: Some(path.clone()),
+            sample_rate: 0.25,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for _ in 0..20 {
+            log.record("api", &[], "rule", &over_limit_status());
+        }
+        log.flush();
+        assert_eq!(read_events(&path).len(), 5);
+    }
+
+    #[test]
+    fn test_audit_file_rotation() {
+        let path = temp_path("rotation");
+        let log = AuditLog::new(AuditConfig {
+            path: Some(path.clone()),
+            max_file_bytes: 300,
+            max_files: 2,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for _ in 0..10 {
+            log.record("api", &[], "rule", &over_limit_status());
+        }
+        log.flush();
+
+        let rotated = |index: usize| PathBuf::from(format!("{}.{}", path.display(), index));
+        assert!(fs::metadata(&path).unwrap().len() <= 300);
+        assert!(rotated(1).exists());
+        assert!(rotated(2).exists());
+        assert!(!rotated(3).exists());
+    }
+
+    #[test]
+    fn test_full_queue_drops_events() {
+        // A queue nobody drains stands in for a writer stuck on slow I/O
+        let (queue, _messages) = mpsc::sync_channel(2);
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let log = AuditLog {
+            config: AuditConfig::default(),
+            queue,
+            seen: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            metrics: Some(metrics.clone()),
+        };
+
+        for _ in 0..5 {
+            log.record("api", &[], "rule", &over_limit_status());
+        }
+        assert_eq!(log.dropped_events(), 3);
+        let dropped = metrics
+            .registry()
+            .gather()
+            .iter()
+            .find(|family| family.get_name() == "ratelimit_audit_events_dropped")
+            .map(|family| family.get_metric()[0].get_counter().get_value());
+        assert_eq!(dropped, Some(3.0));
+    }
+
+    #[test]
+    fn test_config_changes_wait_for_room() {
+        let (queue, messages) = mpsc::sync_channel(1);
+        let log = Arc::new(AuditLog {
+            config: AuditConfig {
+                decisions: false,
+                ..Default::default()
+            },
+            queue,
+            seen: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            metrics: None,
+        });
+
+        // Decisions are not written when disabled
+        log.record("api", &[], "rule", &over_limit_status());
+        let change = |domain: &str| ConfigChangeEvent {
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            action: "delete",
+            domain: domain.to_string(),
+            actor: "admin".to_string(),
+            config: None,
+            persisted: false,
+        };
+        log.record_config_change(&change("first"));
+        // The queue is full: the next change waits until the writer takes a line
+        let writer = {
+            let log = log.clone();
+            thread::spawn(move || log.record_config_change(&change("second")))
+        };
+        let lines: Vec<String> = messages
+            .iter()
+            .take(2)
+            .map(|message| match message {
+                AuditMessage::Line(line) => line,
+                AuditMessage::Flush(_) => panic!("unexpected flush"),
+            })
+            .collect();
+        writer.join().unwrap();
+        assert!(lines[0].contains("\"domain\":\"first\""));
+        assert!(lines[1].contains("\"domain\":\"second\""));
+        assert_eq!(log.dropped_events(), 0);
+    }
+}
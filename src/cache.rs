@@ -1,13 +1,14 @@
 use async_trait::async_trait;
 use moka::{future::Cache, Expiry};
 use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
-use tokio::sync::Mutex;
 
 use crate::{
     config::{CompiledRateLimit},
     error::{RateLimitError, Result},
+    health::DependencyHealth,
+    metrics::Metrics,
     redis::RedisClientPool,
-    utils::{generate_cache_key, get_hits_addend, TimeSource, Unit},
+    utils::{generate_cache_key, get_hits_addend, SystemTimeSource, TimeSource, Unit, Window},
 };
 
 /// Response status for a single descriptor
@@ -17,6 +18,80 @@ pub struct DescriptorStatus {
     pub current_limit: Option<RateLimit>,
     pub limit_remaining: u32,
     pub duration_until_reset_secs: u64,
+    /// Hits have passed the near-limit ratio without going over the limit
+    pub near_limit: bool,
+    /// Over the limit but allowed through because the limit is in shadow mode
+    pub shadow_mode: bool,
+    /// Hits counted in the current window, unknown when served from the local cache
+    pub count: Option<u64>,
+    /// This request's hits split by outcome, for stats
+    pub hits: HitCounts,
+}
+
+impl DescriptorStatus {
+    /// Order statuses of limits on the same descriptor: over-limit first, then the
+    /// fewest remaining hits, then the longest wait until reset
+    fn is_more_restrictive_than(&self, other: &DescriptorStatus) -> bool {
+        let over_limit = |status: &DescriptorStatus| status.code == ResponseCode::OverLimit;
+        (over_limit(self), std::cmp::Reverse(self.limit_remaining), self.duration_until_reset_secs)
+            > (over_limit(other), std::cmp::Reverse(other.limit_remaining), other.duration_until_reset_secs)
+    }
+}
+
+/// Hits of one request split by outcome, counted the way the Go service's stats are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HitCounts {
+    pub total: u64,
+    pub over_limit: u64,
+    pub near_limit: u64,
+    pub within_limit: u64,
+    pub shadow_mode: u64,
+}
+
+impl HitCounts {
+    /// Every hit within the limit
+    pub fn within(hits: u64) -> Self {
+        Self {
+            total: hits,
+            within_limit: hits,
+            ..Self::default()
+        }
+    }
+
+    /// Every hit over the limit
+    pub fn over(hits: u64) -> Self {
+        Self {
+            total: hits,
+            over_limit: hits,
+            ..Self::default()
+        }
+    }
+
+    /// Split the `hits` that took a counter to `count`: only the hits past the
+    /// limit count as over it, and only those past the near-limit threshold as near it
+    pub fn split(count: u64, hits: u64, limit: u64, near_limit: u64, shadow_mode: bool) -> Self {
+        let before = count.saturating_sub(hits);
+        if count > limit {
+            if before >= limit {
+                return Self {
+                    shadow_mode: if shadow_mode { hits } else { 0 },
+                    ..Self::over(hits)
+                };
+            }
+            return Self {
+                total: hits,
+                over_limit: count - limit,
+                near_limit: limit.saturating_sub(near_limit.max(before)),
+                within_limit: 0,
+                shadow_mode: if shadow_mode { hits } else { 0 },
+            };
+        }
+        let near_limit_hits = if count > near_limit { count - near_limit.max(before) } else { 0 };
+        Self {
+            near_limit: near_limit_hits,
+            ..Self::within(hits)
+        }
+    }
 }
 
 /// Response codes for rate limiting
@@ -31,6 +106,21 @@ pub enum ResponseCode {
 pub struct RateLimit {
     pub requests_per_unit: u32,
     pub unit: Unit,
+    pub name: Option<String>,
+    pub window: Window,
+}
+
+/// Live counter of one limit for a descriptor
+#[derive(Debug, Clone)]
+pub struct CounterStatus {
+    pub limit: CompiledRateLimit,
+    /// Cache key of the current window
+    pub key: String,
+    pub count: u64,
+    /// Seconds until the counter expires; `None` when no hits were counted
+    pub ttl_secs: Option<u64>,
+    /// Requests are rejected from the local cache without reaching Redis
+    pub local_over_limit: bool,
 }
 
 /// Rate limit request descriptor
@@ -50,40 +140,61 @@ pub struct RateLimitRequest {
 /// Main trait for rate limit caching
 #[async_trait]
 pub trait RateLimitCache: Send + Sync {
-    /// Perform rate limiting check for the given request
-    async fn do_limit(&self, request: &RateLimitRequest) -> Result<Vec<DescriptorStatus>>;
+    /// Perform rate limiting check for the given request, using the limits resolved
+    /// from configuration for each descriptor (empty if no rule matched)
+    async fn do_limit(
+        &self,
+        request: &RateLimitRequest,
+        limits: &[Vec<CompiledRateLimit>],
+    ) -> Result<Vec<DescriptorStatus>>;
     
     /// Health check for the cache
     async fn health_check(&self) -> Result<()>;
+
+    /// Current-window counters of a descriptor's limits; unlimited limits have no counter
+    async fn counters(
+        &self,
+        domain: &str,
+        entries: &[(String, String)],
+        limits: &[CompiledRateLimit],
+    ) -> Result<Vec<CounterStatus>>;
+
+    /// Set the current-window counters of a descriptor's limits, deleting them when
+    /// `count` is 0, and drop their local over-limit entries
+    async fn set_counters(
+        &self,
+        domain: &str,
+        entries: &[(String, String)],
+        limits: &[CompiledRateLimit],
+        count: u64,
+    ) -> Result<Vec<CounterStatus>>;
+
+    /// Health of each backend the cache depends on
+    async fn dependency_health(&self) -> Vec<DependencyHealth> {
+        vec![DependencyHealth::check("cache", Duration::from_secs(5), self.health_check()).await]
+    }
 }
 
 /// Redis-based rate limit cache implementation
 pub struct RedisRateLimitCache {
     redis_pool: RedisClientPool,
     local_cache: Arc<Cache<String, (Expiration, String)>>,
-    time_source: TimeSource,
+    time_source: Arc<dyn TimeSource>,
+    metrics: Option<Arc<Metrics>>,
     near_limit_ratio: f32,
     cache_key_prefix: String,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Expiration {
-    // The value will pass after 
-    Duration(Unit),     
+    // The value will pass after one window
+    Duration(Window),
 }
 
 impl Expiration {
     pub fn as_duration(&self) -> Option<Duration> {
         match self {
-            Expiration::Duration(unit) => {
-                let seconds = match unit {
-                    Unit::Second => 1,
-                    Unit::Minute => 60,
-                    Unit::Hour =>  3600,
-                    Unit::Day => 86400,
-                };
-                Some(Duration::from_secs(seconds))
-            }
+            Expiration::Duration(window) => Some(Duration::from_secs(window.to_seconds())),
         }
     }
 }
@@ -97,8 +208,7 @@ impl Expiry<String, (Expiration, String)> for MyExpiry {
         value: &(Expiration, String),
         _current_time: Instant,
     ) -> Option<Duration> {
-        let duration = value.0.as_duration();
-        duration
+        value.0.as_duration()
     }
 }
 
@@ -119,56 +229,90 @@ impl RedisRateLimitCache {
         Self {
             redis_pool,
             local_cache: Arc::new(local_cache),
-            time_source: TimeSource::new(),
+            time_source: Arc::new(SystemTimeSource::new()),
+            metrics: None,
             near_limit_ratio,
             cache_key_prefix,
         }
     }
 
-    /// Generate cache keys for descriptors
+    /// Use the given clock for window and reset calculations instead of the system clock
+    pub fn with_time_source(mut self, time_source: Arc<dyn TimeSource>) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Record local cache and Redis metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.redis_pool = self.redis_pool.with_metrics(metrics.clone());
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Generate a cache key for each (descriptor index, limit) check
     fn generate_cache_keys(
         &self,
         request: &RateLimitRequest,
-        limits: &[Option<&CompiledRateLimit>],
-    ) -> Vec<Option<CacheKey>> {
-        limits
+        checks: &[(usize, &CompiledRateLimit)],
+    ) -> Vec<CacheKey> {
+        checks
             .iter()
-            .zip(&request.descriptors)
-            .map(|(limit, descriptor)| {
-                limit.map(|l| {
-                    let descriptors: Vec<(&str, &str)> = descriptor
-                        .entries
-                        .iter()
-                        .map(|(k, v)| (k.as_str(), v.as_str()))
-                        .collect();
-
-                    let key = if self.cache_key_prefix.is_empty() {
-                        generate_cache_key(&request.domain, &descriptors, l.unit, &self.time_source)
-                    } else {
-                        format!(
-                            "{}:{}",
-                            self.cache_key_prefix,
-                            generate_cache_key(&request.domain, &descriptors, l.unit, &self.time_source)
-                        )
-                    };
-
-                    CacheKey {
-                        key,
-                        per_second: l.unit.is_per_second(),
-                    }
-                })
-            })
+            .map(|&(index, l)| self.cache_key(&request.domain, &request.descriptors[index].entries, l))
             .collect()
     }
 
+    /// Cache key of the current window of a limit for a descriptor
+    fn cache_key(&self, domain: &str, entries: &[(String, String)], limit: &CompiledRateLimit) -> CacheKey {
+        let descriptors = limit.cache_key_entries(entries);
+
+        let window = limit.window();
+        let key = if self.cache_key_prefix.is_empty() {
+            generate_cache_key(domain, &descriptors, window, self.time_source.as_ref())
+        } else {
+            format!(
+                "{}:{}",
+                self.cache_key_prefix,
+                generate_cache_key(domain, &descriptors, window, self.time_source.as_ref())
+            )
+        };
+
+        CacheKey {
+            key,
+            per_second: window.is_per_second(),
+        }
+    }
+
+    /// Read the counter behind a cache key
+    async fn counter_status(&self, limit: &CompiledRateLimit, cache_key: CacheKey) -> Result<CounterStatus> {
+        let client = self.redis_pool.get_client(cache_key.per_second);
+        let count = client.get(&cache_key.key).await?.unwrap_or(0);
+        let ttl_secs = client.ttl(&cache_key.key).await?;
+
+        Ok(CounterStatus {
+            limit: limit.clone(),
+            local_over_limit: self.local_cache.contains_key(&cache_key.key),
+            key: cache_key.key,
+            count,
+            ttl_secs,
+        })
+    }
+
     /// Check if a key is over limit in local cache
     async fn is_over_limit_with_local_cache(&self, key: &str) -> bool {
-        self.local_cache.get(key).await.is_some()
+        let hit = self.local_cache.get(key).await.is_some();
+        if let Some(metrics) = &self.metrics {
+            if hit {
+                metrics.record_local_cache_hit();
+            } else {
+                metrics.record_local_cache_miss();
+            }
+        }
+        hit
     }
 
     /// Add a key to the local cache as over-limit
-    async fn add_to_local_cache(&self, key: &str, unit: &Unit) {
-        self.local_cache.insert(key.into(), (Expiration::Duration(unit.clone()), "".into())).await
+    async fn add_to_local_cache(&self, key: &str, window: Window) {
+        self.local_cache.insert(key.into(), (Expiration::Duration(window), "".into())).await
     }
 
     /// Generate response descriptor status
@@ -180,11 +324,13 @@ impl RedisRateLimitCache {
     ) -> DescriptorStatus {
         let current_limit = limit.map(|l| RateLimit {
             requests_per_unit: l.requests_per_unit,
-            unit: l.unit,
+            unit: l.window().envoy_unit(),
+            name: l.name.clone(),
+            window: l.window(),
         });
 
         let duration_until_reset_secs = if let Some(l) = limit {
-            crate::utils::calculate_reset(&l.unit, &self.time_source).as_secs()
+            crate::utils::calculate_reset(l.window(), self.time_source.as_ref()).as_secs()
         } else {
             0
         };
@@ -194,69 +340,75 @@ impl RedisRateLimitCache {
             current_limit,
             limit_remaining,
             duration_until_reset_secs,
+            near_limit: false,
+            shadow_mode: false,
+            count: None,
+            hits: HitCounts::default(),
         }
     }
 }
 
 #[async_trait]
 impl RateLimitCache for RedisRateLimitCache {
-    async fn do_limit(&self, request: &RateLimitRequest) -> Result<Vec<DescriptorStatus>> {
+    async fn do_limit(
+        &self,
+        request: &RateLimitRequest,
+        limits: &[Vec<CompiledRateLimit>],
+    ) -> Result<Vec<DescriptorStatus>> {
         if request.descriptors.is_empty() {
             return Err(RateLimitError::Service(
                 "Rate limit descriptor list must not be empty".to_string(),
             ));
         }
 
-        // For this implementation, we need the compiled limits to be passed in
-        // In a real implementation, these would come from the configuration
-        let limits: Vec<Option<&CompiledRateLimit>> = vec![None; request.descriptors.len()];
+        if limits.len() != request.descriptors.len() {
+            return Err(RateLimitError::Service(
+                "Rate limit list must match the descriptor list".to_string(),
+            ));
+        }
+
+        // One check per (descriptor, limit) pair; all of them are counted together
+        let checks: Vec<(usize, &CompiledRateLimit)> = limits
+            .iter()
+            .enumerate()
+            .flat_map(|(index, descriptor_limits)| descriptor_limits.iter().map(move |l| (index, l)))
+            .collect();
 
-        let cache_keys = self.generate_cache_keys(request, &limits);
+        let cache_keys = self.generate_cache_keys(request, &checks);
         let hits_addend = get_hits_addend(request.hits_addend);
 
-        let mut results = Vec::new();
-        let mut over_limit_local_cache = vec![false; request.descriptors.len()];
+        let mut over_limit_local_cache = vec![false; checks.len()];
 
         // Check local cache for over-limit keys
         for (i, cache_key) in cache_keys.iter().enumerate() {
-            if let Some(key) = cache_key {
-                if self.is_over_limit_with_local_cache(&key.key).await {
-                    over_limit_local_cache[i] = true;
-                }
+            if self.is_over_limit_with_local_cache(&cache_key.key).await {
+                over_limit_local_cache[i] = true;
             }
         }
 
-        // Prepare Redis operations
-        let mut redis_operations = Vec::new();
-        let mut operation_indices = Vec::new();
-
-        for (i, (cache_key, limit)) in cache_keys.iter().zip(&limits).enumerate() {
-            if let (Some(key), Some(limit)) = (cache_key, limit) {
-                if !over_limit_local_cache[i] && !limit.unlimited {
-                    redis_operations.push((
-                        key.key.clone(),
-                        hits_addend,
-                        limit.unit.to_seconds(),
-                    ));
-                    operation_indices.push(i);
-                }
-            }
-        }
-
-        // Execute Redis operations based on per-second vs other units
+        // Prepare Redis operations, split by per-second vs other units
+        let now = self.time_source.utc_now();
         let mut per_second_ops = Vec::new();
         let mut other_ops = Vec::new();
         let mut per_second_indices = Vec::new();
         let mut other_indices = Vec::new();
 
-        for (op_idx, (key, increment, expire)) in redis_operations.iter().enumerate() {
-            let cache_key = cache_keys[operation_indices[op_idx]].as_ref().unwrap();
+        for (i, (cache_key, (_, limit))) in cache_keys.iter().zip(&checks).enumerate() {
+            if over_limit_local_cache[i] || limit.unlimited {
+                continue;
+            }
+
+            let operation = (
+                cache_key.key.clone(),
+                hits_addend,
+                limit.window().expire_seconds(now),
+            );
             if cache_key.per_second {
-                per_second_ops.push((key.clone(), *increment, *expire));
-                per_second_indices.push(operation_indices[op_idx]);
+                per_second_ops.push(operation);
+                per_second_indices.push(i);
             } else {
-                other_ops.push((key.clone(), *increment, *expire));
-                other_indices.push(operation_indices[op_idx]);
+                other_ops.push(operation);
+                other_indices.push(i);
             }
         }
 
@@ -284,62 +436,136 @@ impl RateLimitCache for RedisRateLimitCache {
             redis_result_map.insert(idx, other_results[i]);
         }
 
-        // Generate response statuses
-        for (i, (cache_key, limit)) in cache_keys.iter().zip(&limits).enumerate() {
-            let status = if let (Some(_key), Some(limit)) = (cache_key, limit) {
-                if limit.unlimited {
-                    // Unlimited rate limit
-                    self.generate_response_descriptor_status(ResponseCode::Ok, Some(limit), u32::MAX)
-                } else if over_limit_local_cache[i] {
-                    // Over limit from local cache
-                    self.generate_response_descriptor_status(ResponseCode::OverLimit, Some(limit), 0)
-                } else if let Some(&current_count) = redis_result_map.get(&i) {
-                    // Check Redis result
-                    let over_limit_threshold = limit.requests_per_unit as u64;
-                    let is_over_limit = current_count > over_limit_threshold;
-                    
-                    if is_over_limit && !limit.shadow_mode {
-                        // Add to local cache for future requests
-                        if let Some(key) = cache_key {
-                            self.add_to_local_cache(&key.key, &limit.unit).await;
-                        }
-                        
-                        self.generate_response_descriptor_status(ResponseCode::OverLimit, Some(limit), 0)
-                    } else {
-                        let remaining = if current_count >= over_limit_threshold {
-                            0
-                        } else {
-                            (over_limit_threshold - current_count) as u32
-                        };
-                        
-                        let code = if limit.shadow_mode && is_over_limit {
-                            ResponseCode::Ok  // Shadow mode always returns OK
-                        } else if is_over_limit {
-                            ResponseCode::OverLimit
-                        } else {
-                            ResponseCode::Ok
-                        };
-                        
-                        self.generate_response_descriptor_status(code, Some(limit), remaining)
-                    }
+        // Generate a status per check and keep the most restrictive per descriptor
+        let mut results: Vec<Option<DescriptorStatus>> = vec![None; request.descriptors.len()];
+        for (i, (cache_key, &(index, limit))) in cache_keys.iter().zip(&checks).enumerate() {
+            let status = if limit.unlimited {
+                // Unlimited rate limit
+                let mut status =
+                    self.generate_response_descriptor_status(ResponseCode::Ok, Some(limit), u32::MAX);
+                status.hits = HitCounts::within(hits_addend);
+                status
+            } else if over_limit_local_cache[i] {
+                // Over limit from local cache
+                let mut status =
+                    self.generate_response_descriptor_status(ResponseCode::OverLimit, Some(limit), 0);
+                status.hits = HitCounts::over(hits_addend);
+                status
+            } else if let Some(&current_count) = redis_result_map.get(&i) {
+                // Check Redis result
+                let over_limit_threshold = limit.requests_per_unit as u64;
+                let near_limit_threshold =
+                    (over_limit_threshold as f64 * self.near_limit_ratio as f64).floor() as u64;
+                let is_over_limit = current_count > over_limit_threshold;
+                let hits = HitCounts::split(
+                    current_count,
+                    hits_addend,
+                    over_limit_threshold,
+                    near_limit_threshold,
+                    limit.shadow_mode,
+                );
+
+                if is_over_limit && !limit.shadow_mode {
+                    // Add to local cache for future requests
+                    self.add_to_local_cache(&cache_key.key, limit.window()).await;
+
+                    let mut status =
+                        self.generate_response_descriptor_status(ResponseCode::OverLimit, Some(limit), 0);
+                    status.count = Some(current_count);
+                    status.hits = hits;
+                    status
                 } else {
-                    // No Redis operation (shouldn't happen)
-                    self.generate_response_descriptor_status(ResponseCode::Ok, Some(limit), limit.requests_per_unit)
+                    let remaining = if current_count >= over_limit_threshold {
+                        0
+                    } else {
+                        (over_limit_threshold - current_count) as u32
+                    };
+
+                    // Shadow mode always returns OK
+                    let mut status =
+                        self.generate_response_descriptor_status(ResponseCode::Ok, Some(limit), remaining);
+                    status.shadow_mode = is_over_limit;
+                    status.near_limit = !is_over_limit && current_count > near_limit_threshold;
+                    status.count = Some(current_count);
+                    status.hits = hits;
+                    status
                 }
             } else {
-                // No limit configured - allow through
-                self.generate_response_descriptor_status(ResponseCode::Ok, None, 0)
+                // No Redis operation (shouldn't happen)
+                let mut status = self.generate_response_descriptor_status(
+                    ResponseCode::Ok,
+                    Some(limit),
+                    limit.requests_per_unit,
+                );
+                status.hits = HitCounts::within(hits_addend);
+                status
             };
 
-            results.push(status);
+            results[index] = Some(match results[index].take() {
+                Some(current) if !status.is_more_restrictive_than(&current) => current,
+                _ => status,
+            });
         }
 
-        Ok(results)
+        // Descriptors without a configured limit are allowed through
+        Ok(results
+            .into_iter()
+            .map(|status| {
+                status.unwrap_or_else(|| {
+                    let mut status = self.generate_response_descriptor_status(ResponseCode::Ok, None, 0);
+                    status.hits = HitCounts::within(hits_addend);
+                    status
+                })
+            })
+            .collect())
     }
 
     async fn health_check(&self) -> Result<()> {
         self.redis_pool.health_check().await
     }
+
+    async fn counters(
+        &self,
+        domain: &str,
+        entries: &[(String, String)],
+        limits: &[CompiledRateLimit],
+    ) -> Result<Vec<CounterStatus>> {
+        let mut counters = Vec::new();
+        for limit in limits.iter().filter(|limit| !limit.unlimited) {
+            let cache_key = self.cache_key(domain, entries, limit);
+            counters.push(self.counter_status(limit, cache_key).await?);
+        }
+        Ok(counters)
+    }
+
+    async fn set_counters(
+        &self,
+        domain: &str,
+        entries: &[(String, String)],
+        limits: &[CompiledRateLimit],
+        count: u64,
+    ) -> Result<Vec<CounterStatus>> {
+        let now = self.time_source.utc_now();
+        let mut counters = Vec::new();
+        for limit in limits.iter().filter(|limit| !limit.unlimited) {
+            let cache_key = self.cache_key(domain, entries, limit);
+            let client = self.redis_pool.get_client(cache_key.per_second);
+            if count == 0 {
+                client.delete(&cache_key.key).await?;
+            } else {
+                client
+                    .set_with_expire(&cache_key.key, count, limit.window().expire_seconds(now))
+                    .await?;
+            }
+            self.local_cache.invalidate(&cache_key.key).await;
+            counters.push(self.counter_status(limit, cache_key).await?);
+        }
+        Ok(counters)
+    }
+
+    async fn dependency_health(&self) -> Vec<DependencyHealth> {
+        self.redis_pool.dependency_health().await
+    }
 }
 
 /// Cache key with metadata
@@ -354,6 +580,21 @@ mod tests {
     use super::*;
     use crate::{config::CompiledRateLimit, redis::RedisConfig};
 
+    #[test]
+    fn test_hit_counts_split_at_thresholds() {
+        // Limit 10, near limit from 8: a request of 5 hits taking the count from 6 to 11
+        let hits = HitCounts::split(11, 5, 10, 8, false);
+        assert_eq!(hits, HitCounts { total: 5, over_limit: 1, near_limit: 2, within_limit: 0, shadow_mode: 0 });
+
+        // Already over the limit: every hit is over it
+        assert_eq!(HitCounts::split(15, 4, 10, 8, true), HitCounts { shadow_mode: 4, ..HitCounts::over(4) });
+
+        // Crossing only the near-limit threshold
+        let hits = HitCounts::split(9, 3, 10, 8, false);
+        assert_eq!(hits, HitCounts { near_limit: 1, ..HitCounts::within(3) });
+        assert_eq!(HitCounts::split(5, 3, 10, 8, false), HitCounts::within(3));
+    }
+
     #[tokio::test]
     async fn test_cache_key_generation() {
         let redis_config = RedisConfig::default();
@@ -371,16 +612,87 @@ mod tests {
         let limit = CompiledRateLimit {
             requests_per_unit: 100,
             unit: Unit::Second,
+            unit_multiplier: 1,
+            timezone: None,
             unlimited: false,
             shadow_mode: false,
             name: None,
+            path: "key1_value1".to_string(),
+            detailed_metric: false,
+            shared_values: Vec::new(),
         };
-        let limits = vec![Some(&limit)];
-        let cache_keys = cache.generate_cache_keys(&request, &limits);
+        let checks = vec![(0, &limit)];
+        let cache_keys = cache.generate_cache_keys(&request, &checks);
         assert_eq!(cache_keys.len(), 1);
-        assert!(cache_keys[0].is_some());
-        let cache_key = cache_keys[0].as_ref().unwrap();
+        let cache_key = &cache_keys[0];
         assert!(cache_key.key.contains("test:test_domain:key1_value1:"));
         assert!(cache_key.per_second);
     }
+
+    #[tokio::test]
+    async fn test_cache_records_metrics() {
+        use crate::utils::MockTimeSource;
+
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let redis_pool = RedisClientPool::new_single(RedisConfig::default()).await.unwrap();
+        let prefix = format!("metrics_{}_{}", std::process::id(), SystemTimeSource::new().unix_now());
+        let cache = RedisRateLimitCache::new(redis_pool, 1000, 0.8, prefix)
+            .with_time_source(Arc::new(MockTimeSource::from_unix(1_700_000_000)))
+            .with_metrics(metrics.clone());
+
+        let request = RateLimitRequest {
+            domain: "test_domain".to_string(),
+            descriptors: vec![RateLimitDescriptor {
+                entries: vec![("key1".to_string(), "value1".to_string())],
+            }],
+            hits_addend: 1,
+        };
+        let limits = vec![vec![CompiledRateLimit {
+            requests_per_unit: 1,
+            unit: Unit::Minute,
+            unit_multiplier: 1,
+            timezone: None,
+            unlimited: false,
+            shadow_mode: false,
+            name: None,
+            path: "key1_value1".to_string(),
+            detailed_metric: false,
+            shared_values: Vec::new(),
+        }]];
+
+        // Within limit, then over limit, then served from the local cache
+        for _ in 0..3 {
+            cache.do_limit(&request, &limits).await.unwrap();
+        }
+
+        let families = metrics.registry().gather();
+        let value = |name: &str, labels: &[(&str, &str)]| -> f64 {
+            families
+                .iter()
+                .filter(|f| f.get_name() == name)
+                .flat_map(|f| f.get_metric())
+                .filter(|m| {
+                    labels.iter().all(|(k, v)| {
+                        m.get_label().iter().any(|l| l.get_name() == *k && l.get_value() == *v)
+                    })
+                })
+                .map(|m| {
+                    if m.has_gauge() {
+                        m.get_gauge().get_value()
+                    } else if m.has_histogram() {
+                        m.get_histogram().get_sample_count() as f64
+                    } else {
+                        m.get_counter().get_value()
+                    }
+                })
+                .sum()
+        };
+
+        let pipeline = [("operation", "pipeline"), ("result", "success")];
+        assert_eq!(value("ratelimit_redis_operations", &pipeline), 2.0);
+        assert_eq!(value("ratelimit_redis_operation_duration_seconds", &[("operation", "pipeline")]), 2.0);
+        assert_eq!(value("ratelimit_redis_connections_active", &[("instance", "primary")]), 1.0);
+        assert_eq!(value("ratelimit_local_cache_misses", &[]), 2.0);
+        assert_eq!(value("ratelimit_local_cache_hits", &[]), 1.0);
+    }
 }
\ No newline at end of file
@@ -1,6 +1,12 @@
+use chrono_tz::Tz;
+use ipnet::IpNet;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use crate::{error::Result, utils::Unit};
+use std::{collections::HashMap, net::IpAddr, path::Path};
+use crate::{
+    error::{RateLimitError, Result},
+    utils::{Unit, Window},
+};
 
 /// Rate limit configuration for a domain
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,12 +16,41 @@ pub struct RateLimitConfig {
 }
 
 /// A rate limit descriptor that can match requests
+///
+/// A request entry with a matching key is matched against, in order of precedence:
+/// an exact `value`, then the first `value_prefix`, `value_regex` or `value_cidr`
+/// sibling (in configuration order) that matches, then a sibling with no value at
+/// all, which matches any value. At most one value field may be set.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RateLimitDescriptor {
     pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub value: Option<String>,
+    /// Match values starting with this prefix, e.g. `/api/v1/`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_prefix: Option<String>,
+    /// Match values against this regular expression, which must match the whole value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_regex: Option<String>,
+    /// Match IP address values inside this network, e.g. `10.0.0.0/8`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_cidr: Option<String>,
+    /// Count every value matched by this descriptor's pattern (or wildcard) in one
+    /// shared counter instead of one counter per distinct value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_threshold: Option<bool>,
+    /// Label metrics for this descriptor with the full request path and values
+    /// instead of the rule name (high cardinality, use sparingly)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detailed_metric: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rate_limit: Option<RateLimit>,
+    /// Additional limits enforced together with `rate_limit`, e.g. per second and per day
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limits: Option<Vec<RateLimit>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub shadow_mode: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub descriptors: Option<Vec<RateLimitDescriptor>>,
 }
 
@@ -24,7 +59,18 @@ pub struct RateLimitDescriptor {
 pub struct RateLimit {
     pub requests_per_unit: u32,
     pub unit: RateLimitUnit,
+    /// Number of units in one window, e.g. `unit: second` with `unit_multiplier: 15`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_multiplier: Option<u32>,
+    /// Align day and week windows to local midnight instead of fixed intervals
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calendar: Option<bool>,
+    /// IANA timezone for calendar windows, e.g. `America/New_York` (default UTC)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub unlimited: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 }
 
@@ -36,6 +82,8 @@ pub enum RateLimitUnit {
     Minute,
     Hour,
     Day,
+    Week,
+    Month,
 }
 
 impl From<RateLimitUnit> for Unit {
@@ -45,6 +93,8 @@ impl From<RateLimitUnit> for Unit {
             RateLimitUnit::Minute => Unit::Minute,
             RateLimitUnit::Hour => Unit::Hour,
             RateLimitUnit::Day => Unit::Day,
+            RateLimitUnit::Week => Unit::Week,
+            RateLimitUnit::Month => Unit::Month,
         }
     }
 }
@@ -56,6 +106,8 @@ impl From<Unit> for RateLimitUnit {
             Unit::Minute => RateLimitUnit::Minute,
             Unit::Hour => RateLimitUnit::Hour,
             Unit::Day => RateLimitUnit::Day,
+            Unit::Week => RateLimitUnit::Week,
+            Unit::Month => RateLimitUnit::Month,
         }
     }
 }
@@ -64,114 +116,500 @@ impl From<Unit> for RateLimitUnit {
 #[derive(Debug)]
 pub struct CompiledRateLimitConfig {
     domain: String,
-    // Map from descriptor path to rate limit
-    limits: HashMap<String, CompiledRateLimit>,
+    // Configuration this was compiled from
+    source: RateLimitConfig,
+    // Top-level descriptors, nested descriptors hang off each node
+    descriptors: DescriptorMatchers,
+}
+
+/// A compiled descriptor: its limits and the nested descriptors below it
+#[derive(Debug, Default)]
+struct CompiledDescriptor {
+    limits: Vec<CompiledRateLimit>,
+    children: DescriptorMatchers,
+}
+
+/// Sibling descriptors grouped by how they match a request entry's value
+#[derive(Debug, Default)]
+struct DescriptorMatchers {
+    // key -> value -> descriptor
+    exact: HashMap<String, HashMap<String, CompiledDescriptor>>,
+    // (key, matcher, descriptor) in configuration order
+    patterns: Vec<(String, ValueMatcher, CompiledDescriptor)>,
+    // key -> descriptor matching any value
+    wildcard: HashMap<String, CompiledDescriptor>,
+}
+
+impl DescriptorMatchers {
+    /// Find the descriptor for a request entry: exact, then pattern, then wildcard
+    fn find(&self, key: &str, value: &str) -> Option<&CompiledDescriptor> {
+        self.exact
+            .get(key)
+            .and_then(|values| values.get(value))
+            .or_else(|| {
+                self.patterns
+                    .iter()
+                    .find(|(pattern_key, matcher, _)| pattern_key == key && matcher.matches(value))
+                    .map(|(_, _, descriptor)| descriptor)
+            })
+            .or_else(|| self.wildcard.get(key))
+    }
+
+    /// Collect the limits of every descriptor at or below this level
+    fn collect_limits<'a>(&'a self, limits: &mut Vec<&'a CompiledRateLimit>) {
+        let descriptors = self
+            .exact
+            .values()
+            .flat_map(|values| values.values())
+            .chain(self.patterns.iter().map(|(_, _, descriptor)| descriptor))
+            .chain(self.wildcard.values());
+        for descriptor in descriptors {
+            limits.extend(&descriptor.limits);
+            descriptor.children.collect_limits(limits);
+        }
+    }
+}
+
+/// Compiled non-exact value match
+#[derive(Debug)]
+enum ValueMatcher {
+    Prefix(String),
+    Regex(Regex),
+    Cidr(IpNet),
+}
+
+impl ValueMatcher {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            ValueMatcher::Prefix(prefix) => value.starts_with(prefix.as_str()),
+            ValueMatcher::Regex(regex) => regex.is_match(value),
+            ValueMatcher::Cidr(network) => value
+                .parse::<IpAddr>()
+                .map(|address| network.contains(&address))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// How a configured descriptor matches values, before insertion into the tree
+enum DescriptorMatch {
+    Exact(String),
+    Pattern(ValueMatcher),
+    Any,
 }
 
 #[derive(Debug, Clone)]
 pub struct CompiledRateLimit {
     pub requests_per_unit: u32,
     pub unit: Unit,
+    pub unit_multiplier: u32,
+    /// Timezone of a calendar-aligned window; `None` for fixed-length windows
+    pub timezone: Option<Tz>,
     pub unlimited: bool,
     pub shadow_mode: bool,
     pub name: Option<String>,
+    /// Path of the config descriptor this limit belongs to, e.g. `api_public:endpoint`
+    pub path: String,
+    pub detailed_metric: bool,
+    /// Per descriptor level, the config pattern used in cache keys in place of the
+    /// request value when that level shares one counter (`share_threshold`)
+    pub shared_values: Vec<Option<String>>,
+}
+
+impl CompiledRateLimit {
+    /// Descriptor entries as they appear in cache keys, with the values of shared
+    /// levels replaced by their config pattern
+    pub fn cache_key_entries<'a>(&'a self, entries: &'a [(String, String)]) -> Vec<(&'a str, &'a str)> {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, (key, value))| match self.shared_values.get(i) {
+                Some(Some(shared)) => (key.as_str(), shared.as_str()),
+                _ => (key.as_str(), value.as_str()),
+            })
+            .collect()
+    }
+
+    /// The time window this limit is counted over
+    pub fn window(&self) -> Window {
+        match self.timezone {
+            Some(tz) => Window::calendar(self.unit, tz),
+            None => Window::new(self.unit, self.unit_multiplier),
+        }
+    }
 }
 
 impl CompiledRateLimitConfig {
     /// Compile a configuration for efficient runtime lookups
     pub fn compile(config: RateLimitConfig) -> Result<Self> {
-        let mut limits = HashMap::new();
+        let source = config.clone();
+        let mut descriptors = DescriptorMatchers::default();
         
         for descriptor in &config.descriptors {
-            Self::compile_descriptor(descriptor, &mut vec![], &mut limits)?;
+            Self::compile_descriptor(descriptor, &mut vec![], &mut vec![], &mut descriptors)?;
         }
 
         Ok(Self {
             domain: config.domain,
-            limits,
+            source,
+            descriptors,
         })
     }
 
     fn compile_descriptor(
         descriptor: &RateLimitDescriptor,
         path: &mut Vec<String>,
-        limits: &mut HashMap<String, CompiledRateLimit>,
+        shared_values: &mut Vec<Option<String>>,
+        matchers: &mut DescriptorMatchers,
     ) -> Result<()> {
         // Add current descriptor to path
-        let key_value = if let Some(value) = &descriptor.value {
-            format!("{}_{}", descriptor.key, value)
+        let (value_label, value_match) = Self::compile_value_match(descriptor)?;
+        if value_label.is_empty() {
+            path.push(descriptor.key.clone());
+        } else {
+            path.push(format!("{}_{}", descriptor.key, value_label));
+        }
+
+        let shared_value = if descriptor.share_threshold.unwrap_or(false) {
+            if matches!(value_match, DescriptorMatch::Exact(_)) {
+                return Err(RateLimitError::Config(format!(
+                    "share_threshold requires a wildcard or pattern value for descriptor '{}'",
+                    path.join(":")
+                )));
+            }
+            Some(if value_label.is_empty() { "*".to_string() } else { value_label })
         } else {
-            descriptor.key.clone()
+            None
+        };
+        shared_values.push(shared_value);
+
+        let node = match value_match {
+            DescriptorMatch::Exact(value) => matchers
+                .exact
+                .entry(descriptor.key.clone())
+                .or_default()
+                .entry(value)
+                .or_default(),
+            DescriptorMatch::Pattern(matcher) => {
+                matchers
+                    .patterns
+                    .push((descriptor.key.clone(), matcher, CompiledDescriptor::default()));
+                &mut matchers.patterns.last_mut().expect("pattern was just pushed").2
+            }
+            DescriptorMatch::Any => matchers.wildcard.entry(descriptor.key.clone()).or_default(),
         };
-        path.push(key_value);
 
-        // If this descriptor has a rate limit, store it
-        if let Some(rate_limit) = &descriptor.rate_limit {
+        // If this descriptor has rate limits, store them
+        let rate_limits: Vec<&RateLimit> = descriptor
+            .rate_limit
+            .iter()
+            .chain(descriptor.rate_limits.iter().flatten())
+            .collect();
+        if !rate_limits.is_empty() {
             let path_key = path.join(":");
-            limits.insert(
-                path_key,
-                CompiledRateLimit {
-                    requests_per_unit: rate_limit.requests_per_unit,
-                    unit: rate_limit.unit.clone().into(),
-                    unlimited: rate_limit.unlimited.unwrap_or(false),
-                    shadow_mode: descriptor.shadow_mode.unwrap_or(false),
-                    name: rate_limit.name.clone(),
-                },
-            );
+            let mut compiled: Vec<CompiledRateLimit> = Vec::with_capacity(rate_limits.len());
+            for rate_limit in rate_limits {
+                let limit = Self::compile_rate_limit(rate_limit, descriptor, &path_key, shared_values)?;
+                // Limits sharing a window would share a counter and double count hits
+                if compiled.iter().any(|other| other.window() == limit.window()) {
+                    return Err(RateLimitError::Config(format!(
+                        "duplicate {} window in rate limits for descriptor '{}'",
+                        limit.unit.as_str(),
+                        path_key
+                    )));
+                }
+                compiled.push(limit);
+            }
+            node.limits = compiled;
         }
 
         // Recursively compile nested descriptors
         if let Some(nested_descriptors) = &descriptor.descriptors {
             for nested in nested_descriptors {
-                Self::compile_descriptor(nested, path, limits)?;
+                Self::compile_descriptor(nested, path, shared_values, &mut node.children)?;
             }
         }
 
         path.pop();
+        shared_values.pop();
         Ok(())
     }
 
+    /// Resolve how a descriptor matches values, along with a label for the matched
+    /// value (empty for wildcards) used in its path
+    fn compile_value_match(descriptor: &RateLimitDescriptor) -> Result<(String, DescriptorMatch)> {
+        let key = &descriptor.key;
+        let value_fields = [
+            descriptor.value.is_some(),
+            descriptor.value_prefix.is_some(),
+            descriptor.value_regex.is_some(),
+            descriptor.value_cidr.is_some(),
+        ];
+        if value_fields.iter().filter(|set| **set).count() > 1 {
+            return Err(RateLimitError::Config(format!(
+                "descriptor '{}' may set only one of value, value_prefix, value_regex and value_cidr",
+                key
+            )));
+        }
+
+        if let Some(value) = &descriptor.value {
+            Ok((value.clone(), DescriptorMatch::Exact(value.clone())))
+        } else if let Some(prefix) = &descriptor.value_prefix {
+            Ok((
+                format!("{}*", prefix),
+                DescriptorMatch::Pattern(ValueMatcher::Prefix(prefix.clone())),
+            ))
+        } else if let Some(pattern) = &descriptor.value_regex {
+            let regex = Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| {
+                RateLimitError::Config(format!(
+                    "invalid value_regex '{}' for descriptor '{}': {}",
+                    pattern, key, e
+                ))
+            })?;
+            Ok((
+                format!("~{}", pattern),
+                DescriptorMatch::Pattern(ValueMatcher::Regex(regex)),
+            ))
+        } else if let Some(cidr) = &descriptor.value_cidr {
+            let network = cidr.parse::<IpNet>().map_err(|e| {
+                RateLimitError::Config(format!(
+                    "invalid value_cidr '{}' for descriptor '{}': {}",
+                    cidr, key, e
+                ))
+            })?;
+            Ok((
+                network.to_string(),
+                DescriptorMatch::Pattern(ValueMatcher::Cidr(network)),
+            ))
+        } else {
+            Ok((String::new(), DescriptorMatch::Any))
+        }
+    }
+
+    fn compile_rate_limit(
+        rate_limit: &RateLimit,
+        descriptor: &RateLimitDescriptor,
+        path_key: &str,
+        shared_values: &[Option<String>],
+    ) -> Result<CompiledRateLimit> {
+        let unit_multiplier = rate_limit.unit_multiplier.unwrap_or(1);
+        if unit_multiplier == 0 {
+            return Err(RateLimitError::Config(format!(
+                "unit_multiplier must be greater than zero for descriptor '{}'",
+                path_key
+            )));
+        }
+        let timezone = Self::compile_timezone(rate_limit, unit_multiplier, path_key)?;
+
+        Ok(CompiledRateLimit {
+            requests_per_unit: rate_limit.requests_per_unit,
+            unit: rate_limit.unit.clone().into(),
+            unit_multiplier,
+            timezone,
+            unlimited: rate_limit.unlimited.unwrap_or(false),
+            shadow_mode: descriptor.shadow_mode.unwrap_or(false),
+            name: rate_limit.name.clone(),
+            path: path_key.to_string(),
+            detailed_metric: descriptor.detailed_metric.unwrap_or(false),
+            shared_values: shared_values.to_vec(),
+        })
+    }
+
+    /// Resolve the timezone of a calendar window. Months are always calendar
+    /// windows; days and weeks are when `calendar` or `timezone` is set.
+    fn compile_timezone(
+        rate_limit: &RateLimit,
+        unit_multiplier: u32,
+        path_key: &str,
+    ) -> Result<Option<Tz>> {
+        let unit: Unit = rate_limit.unit.clone().into();
+        let calendar = rate_limit.calendar.unwrap_or(false)
+            || rate_limit.timezone.is_some()
+            || unit == Unit::Month;
+        if !calendar {
+            return Ok(None);
+        }
+
+        if !unit.supports_calendar() {
+            return Err(RateLimitError::Config(format!(
+                "calendar windows require a day, week or month unit for descriptor '{}'",
+                path_key
+            )));
+        }
+        if unit_multiplier != 1 {
+            return Err(RateLimitError::Config(format!(
+                "unit_multiplier cannot be combined with a calendar window for descriptor '{}'",
+                path_key
+            )));
+        }
+
+        match &rate_limit.timezone {
+            Some(name) => name.parse::<Tz>().map(Some).map_err(|_| {
+                RateLimitError::Config(format!(
+                    "unknown timezone '{}' for descriptor '{}'",
+                    name, path_key
+                ))
+            }),
+            None => Ok(Some(Tz::UTC)),
+        }
+    }
+
     /// Get the domain for this configuration
     pub fn domain(&self) -> &str {
         &self.domain
     }
 
-    /// Find a rate limit for the given descriptor path
+    /// The configuration this was compiled from
+    pub fn source(&self) -> &RateLimitConfig {
+        &self.source
+    }
+
+    /// All configured limits, ordered by descriptor path
+    pub fn limits(&self) -> Vec<&CompiledRateLimit> {
+        let mut limits = Vec::new();
+        self.descriptors.collect_limits(&mut limits);
+        // Stable, so multiple limits on one descriptor keep their configured order
+        limits.sort_by(|a, b| a.path.cmp(&b.path));
+        limits
+    }
+
+    /// Find the first rate limit for the given descriptor path
     pub fn find_limit(&self, descriptors: &[(&str, &str)]) -> Option<&CompiledRateLimit> {
-        // Try different combinations, from most specific to least specific
-        for i in (1..=descriptors.len()).rev() {
-            let path_parts: Vec<String> = descriptors[..i]
-                .iter()
-                .map(|(key, value)| {
-                    if value.is_empty() {
-                        key.to_string()
-                    } else {
-                        format!("{}_{}", key, value)
-                    }
-                })
-                .collect();
-            
-            let path = path_parts.join(":");
-            if let Some(limit) = self.limits.get(&path) {
-                return Some(limit);
+        self.find_limits(descriptors).first()
+    }
+
+    /// Find all rate limits for the given descriptor path, taken from the deepest
+    /// matching descriptor that has limits
+    pub fn find_limits(&self, descriptors: &[(&str, &str)]) -> &[CompiledRateLimit] {
+        let mut matchers = &self.descriptors;
+        let mut limits: &[CompiledRateLimit] = &[];
+
+        for (key, value) in descriptors {
+            let Some(descriptor) = matchers.find(key, value) else {
+                break;
+            };
+            if !descriptor.limits.is_empty() {
+                limits = &descriptor.limits;
             }
+            matchers = &descriptor.children;
         }
         
-        None
+        limits
     }
 }
 
+/// Config file format, detected from the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Format for a `.yaml`/`.yml`, `.json` or `.toml` path
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Yaml => "YAML",
+            Self::Json => "JSON",
+            Self::Toml => "TOML",
+        }
+    }
+
+    /// Parse a configuration, reporting where parsing failed as line and column
+    pub fn parse(&self, content: &str) -> Result<RateLimitConfig> {
+        match self {
+            Self::Yaml => serde_yaml::from_str(content).map_err(|e| {
+                let location = e.location().map(|location| (location.line(), location.column()));
+                self.parse_error(e.to_string(), location)
+            }),
+            Self::Json => serde_json::from_str(content).map_err(|e| {
+                let location = (e.line() > 0).then(|| (e.line(), e.column()));
+                self.parse_error(e.to_string(), location)
+            }),
+            Self::Toml => toml::from_str(content).map_err(|e| {
+                let location = e.span().map(|span| line_column(content, span.start));
+                self.parse_error(e.message().to_string(), location)
+            }),
+        }
+    }
+
+    /// Write a configuration as it would appear in a config file
+    pub fn serialize(&self, config: &RateLimitConfig) -> Result<String> {
+        let serialized = match self {
+            Self::Yaml => serde_yaml::to_string(config).map_err(|e| e.to_string()),
+            Self::Json => serde_json::to_string_pretty(config).map_err(|e| e.to_string()),
+            Self::Toml => toml::to_string(config).map_err(|e| e.to_string()),
+        };
+        serialized.map_err(|e| {
+            RateLimitError::Config(format!("Failed to serialize config as {}: {}", self.as_str(), e))
+        })
+    }
+
+    fn parse_error(&self, message: String, location: Option<(usize, usize)>) -> RateLimitError {
+        let Some((line, column)) = location else {
+            return RateLimitError::Config(format!("Failed to parse {}: {}", self.as_str(), message));
+        };
+        // YAML and JSON errors end with their own location; report it once, up front
+        let suffix = format!(" at line {} column {}", line, column);
+        let message = message.strip_suffix(&suffix).unwrap_or(&message);
+        RateLimitError::Config(format!(
+            "Failed to parse {} at line {}, column {}: {}",
+            self.as_str(),
+            line,
+            column,
+            message.trim_end()
+        ))
+    }
+}
+
+/// 1-based line and column of a byte offset
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
 /// Load configuration from YAML string
 pub fn load_config_from_yaml(yaml: &str) -> Result<RateLimitConfig> {
-    serde_yaml::from_str(yaml).map_err(|e| {
-        crate::error::RateLimitError::Config(format!("Failed to parse YAML: {}", e))
-    })
+    ConfigFormat::Yaml.parse(yaml)
+}
+
+/// Load configuration from JSON string
+pub fn load_config_from_json(json: &str) -> Result<RateLimitConfig> {
+    ConfigFormat::Json.parse(json)
+}
+
+/// Load configuration from TOML string
+pub fn load_config_from_toml(toml: &str) -> Result<RateLimitConfig> {
+    ConfigFormat::Toml.parse(toml)
 }
 
-/// Load configuration from YAML file
-pub fn load_config_from_file(path: &str) -> Result<RateLimitConfig> {
-    let content = std::fs::read_to_string(path)?;
-    load_config_from_yaml(&content)
+/// Load configuration from a file, in the format given by its extension.
+/// Files without a known extension are read as YAML.
+pub fn load_config_from_file(path: impl AsRef<Path>) -> Result<RateLimitConfig> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        RateLimitError::Config(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    ConfigFormat::from_path(path)
+        .unwrap_or(ConfigFormat::Yaml)
+        .parse(&content)
+        .map_err(|e| match e {
+            RateLimitError::Config(message) => {
+                RateLimitError::Config(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
 }
 
 #[cfg(test)]
@@ -207,12 +645,21 @@ descriptors:
                 RateLimitDescriptor {
                     key: "database".to_string(),
                     value: Some("users".to_string()),
+                    value_prefix: None,
+                    value_regex: None,
+                    value_cidr: None,
+                    share_threshold: None,
+                    detailed_metric: None,
                     rate_limit: Some(RateLimit {
                         requests_per_unit: 100,
                         unit: RateLimitUnit::Second,
+                        unit_multiplier: None,
+                        calendar: None,
+                        timezone: None,
                         unlimited: None,
                         name: None,
                     }),
+                    rate_limits: None,
                     shadow_mode: None,
                     descriptors: None,
                 },
@@ -224,4 +671,372 @@ descriptors:
         assert!(limit.is_some());
         assert_eq!(limit.unwrap().requests_per_unit, 100);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_unit_multiplier() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: api
+    rate_limit:
+      requests_per_unit: 10
+      unit: second
+      unit_multiplier: 15
+  - key: export
+    rate_limit:
+      requests_per_unit: 1000
+      unit: day
+      unit_multiplier: 7
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+
+        let limit = compiled.find_limit(&[("api", "")]).unwrap();
+        assert_eq!(limit.window().to_seconds(), 15);
+
+        let limit = compiled.find_limit(&[("export", "")]).unwrap();
+        assert_eq!(limit.window().to_seconds(), 7 * 86400);
+        assert_eq!(limit.window().envoy_unit(), Unit::Day);
+    }
+
+    #[test]
+    fn test_zero_unit_multiplier_rejected() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: api
+    rate_limit:
+      requests_per_unit: 10
+      unit: second
+      unit_multiplier: 0
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        assert!(CompiledRateLimitConfig::compile(config).is_err());
+    }
+
+    #[test]
+    fn test_calendar_windows() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: tenant
+    value: acme
+    rate_limit:
+      requests_per_unit: 1000
+      unit: day
+      timezone: America/New_York
+  - key: tenant
+    rate_limit:
+      requests_per_unit: 50000
+      unit: month
+  - key: plan
+    rate_limit:
+      requests_per_unit: 100
+      unit: week
+      calendar: true
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+
+        let limit = compiled.find_limit(&[("tenant", "acme")]).unwrap();
+        assert_eq!(limit.window(), Window::calendar(Unit::Day, chrono_tz::America::New_York));
+
+        let limit = compiled.find_limit(&[("tenant", "")]).unwrap();
+        assert_eq!(limit.window(), Window::calendar(Unit::Month, Tz::UTC));
+
+        let limit = compiled.find_limit(&[("plan", "")]).unwrap();
+        assert_eq!(limit.window(), Window::calendar(Unit::Week, Tz::UTC));
+    }
+
+    #[test]
+    fn test_list_all_limits() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: user
+    rate_limits:
+      - { requests_per_unit: 5, unit: second }
+      - { requests_per_unit: 100, unit: minute }
+  - key: path
+    value_prefix: /api/
+    descriptors:
+      - key: method
+        value: POST
+        rate_limit: { requests_per_unit: 1, unit: second, name: api_writes }
+  - key: api_key
+    value: internal
+    rate_limit: { unlimited: true, requests_per_unit: 0, unit: second }
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+
+        let limits: Vec<(&str, u32)> = compiled
+            .limits()
+            .into_iter()
+            .map(|limit| (limit.path.as_str(), limit.requests_per_unit))
+            .collect();
+        assert_eq!(
+            limits,
+            [("api_key_internal", 0), ("path_/api/*:method_POST", 1), ("user", 5), ("user", 100)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_calendar_windows_rejected() {
+        for rate_limit in [
+            "{ requests_per_unit: 1, unit: day, timezone: Mars/Olympus_Mons }",
+            "{ requests_per_unit: 1, unit: hour, calendar: true }",
+            "{ requests_per_unit: 1, unit: day, unit_multiplier: 2, calendar: true }",
+        ] {
+            let yaml = format!(
+                "domain: test\ndescriptors:\n  - key: api\n    rate_limit: {}\n",
+                rate_limit
+            );
+            let config = load_config_from_yaml(&yaml).unwrap();
+            assert!(CompiledRateLimitConfig::compile(config).is_err(), "{}", rate_limit);
+        }
+    }
+
+    #[test]
+    fn test_multiple_limits_per_descriptor() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: api_key
+    rate_limits:
+      - requests_per_unit: 10
+        unit: second
+      - requests_per_unit: 10000
+        unit: day
+  - key: user
+    rate_limit:
+      requests_per_unit: 5
+      unit: minute
+    rate_limits:
+      - requests_per_unit: 100
+        unit: hour
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+
+        let limits = compiled.find_limits(&[("api_key", "")]);
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits[0].unit, Unit::Second);
+        assert_eq!(limits[1].unit, Unit::Day);
+
+        let limits = compiled.find_limits(&[("user", "")]);
+        assert_eq!(limits.len(), 2);
+        assert_eq!(compiled.find_limit(&[("user", "")]).unwrap().requests_per_unit, 5);
+
+        assert!(compiled.find_limits(&[("other", "")]).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_window_rejected() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: api_key
+    rate_limits:
+      - requests_per_unit: 10
+        unit: minute
+      - requests_per_unit: 20
+        unit: minute
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        assert!(CompiledRateLimitConfig::compile(config).is_err());
+    }
+
+    #[test]
+    fn test_value_match_precedence() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: path
+    value: /api/v1/health
+    rate_limit:
+      requests_per_unit: 1000
+      unit: second
+  - key: path
+    value_prefix: /api/v1/
+    rate_limit:
+      requests_per_unit: 100
+      unit: second
+  - key: path
+    value_regex: /api/v[0-9]+/.*
+    rate_limit:
+      requests_per_unit: 50
+      unit: second
+  - key: path
+    rate_limit:
+      requests_per_unit: 10
+      unit: second
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+        let limit = |value| compiled.find_limit(&[("path", value)]).unwrap().requests_per_unit;
+
+        assert_eq!(limit("/api/v1/health"), 1000);
+        assert_eq!(limit("/api/v1/users"), 100);
+        assert_eq!(limit("/api/v2/users"), 50);
+        assert_eq!(limit("/static/app.js"), 10);
+    }
+
+    #[test]
+    fn test_regex_and_cidr_matching() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: user_agent
+    value_regex: "curl/.*"
+    rate_limit:
+      requests_per_unit: 1
+      unit: second
+  - key: remote_address
+    value_cidr: 10.0.0.0/8
+    descriptors:
+      - key: path
+        value_prefix: /admin
+        rate_limit:
+          requests_per_unit: 5
+          unit: minute
+  - key: remote_address
+    value_cidr: "2001:db8::/32"
+    rate_limit:
+      requests_per_unit: 7
+      unit: minute
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+
+        assert!(compiled.find_limit(&[("user_agent", "curl/8.4.0")]).is_some());
+        // Regexes must match the whole value
+        assert!(compiled.find_limit(&[("user_agent", "not-curl/8.4.0")]).is_none());
+
+        let limit = compiled.find_limit(&[("remote_address", "10.1.2.3"), ("path", "/admin/users")]);
+        assert_eq!(limit.unwrap().requests_per_unit, 5);
+        assert!(compiled.find_limit(&[("remote_address", "192.168.1.1"), ("path", "/admin")]).is_none());
+        assert!(compiled.find_limit(&[("remote_address", "not-an-ip")]).is_none());
+        assert_eq!(compiled.find_limit(&[("remote_address", "2001:db8::1")]).unwrap().requests_per_unit, 7);
+    }
+
+    #[test]
+    fn test_invalid_value_matchers_rejected() {
+        for descriptor in [
+            "{ key: ua, value_regex: \"(unclosed\" }",
+            "{ key: ip, value_cidr: 10.0.0.0/33 }",
+            "{ key: path, value: /a, value_prefix: /a }",
+        ] {
+            let yaml = format!("domain: test\ndescriptors:\n  - {}\n", descriptor);
+            let config = load_config_from_yaml(&yaml).unwrap();
+            assert!(CompiledRateLimitConfig::compile(config).is_err(), "{}", descriptor);
+        }
+    }
+
+    #[test]
+    fn test_share_threshold() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: path
+    value_prefix: /files/
+    share_threshold: true
+    rate_limit:
+      requests_per_unit: 10
+      unit: second
+  - key: user
+    share_threshold: true
+    descriptors:
+      - key: action
+        rate_limit:
+          requests_per_unit: 5
+          unit: minute
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+
+        let entries = vec![("path".to_string(), "/files/a.txt".to_string())];
+        let limit = compiled.find_limit(&[("path", "/files/a.txt")]).unwrap();
+        assert_eq!(limit.cache_key_entries(&entries), vec![("path", "/files/*")]);
+
+        let entries = vec![
+            ("user".to_string(), "alice".to_string()),
+            ("action".to_string(), "upload".to_string()),
+        ];
+        let limit = compiled.find_limit(&[("user", "alice"), ("action", "upload")]).unwrap();
+        assert_eq!(limit.cache_key_entries(&entries), vec![("user", "*"), ("action", "upload")]);
+    }
+
+    #[test]
+    fn test_share_threshold_requires_pattern() {
+        let yaml = r#"
+domain: test
+descriptors:
+  - key: path
+    value: /files/a.txt
+    share_threshold: true
+    rate_limit:
+      requests_per_unit: 10
+      unit: second
+"#;
+
+        let config = load_config_from_yaml(yaml).unwrap();
+        assert!(CompiledRateLimitConfig::compile(config).is_err());
+    }
+
+    #[test]
+    fn test_config_formats() {
+        assert_eq!(ConfigFormat::from_path(Path::new("a/api.yml")), Some(ConfigFormat::Yaml));
+        assert_eq!(ConfigFormat::from_path(Path::new("api.JSON")), Some(ConfigFormat::Json));
+        assert_eq!(ConfigFormat::from_path(Path::new("api.toml")), Some(ConfigFormat::Toml));
+        assert_eq!(ConfigFormat::from_path(Path::new("api.conf")), None);
+
+        let toml = r#"
+domain = "test"
+
+[[descriptors]]
+key = "user"
+rate_limit = { requests_per_unit = 10, unit = "minute" }
+
+[[descriptors.descriptors]]
+key = "path"
+value = "/upload"
+rate_limits = [{ requests_per_unit = 1, unit = "second" }]
+"#;
+        let config = load_config_from_toml(toml).unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::to_string(&load_config_from_json(&json).unwrap()).unwrap(), json);
+        for format in [ConfigFormat::Yaml, ConfigFormat::Json, ConfigFormat::Toml] {
+            let written = format.serialize(&config).unwrap();
+            let reparsed = format.parse(&written).unwrap();
+            assert_eq!(serde_json::to_string(&reparsed).unwrap(), json, "{}", format.as_str());
+        }
+        let compiled = CompiledRateLimitConfig::compile(config).unwrap();
+        assert_eq!(compiled.find_limit(&[("user", "alice")]).unwrap().requests_per_unit, 10);
+        assert_eq!(compiled.find_limit(&[("user", "alice"), ("path", "/upload")]).unwrap().requests_per_unit, 1);
+    }
+
+    #[test]
+    fn test_parse_errors_report_location() {
+        let yaml = "domain: test\ndescriptors:\n  - key: [user]\n";
+        let err = load_config_from_yaml(yaml).unwrap_err().to_string();
+        assert!(err.contains("Failed to parse YAML at line 3, column 10: "), "{}", err);
+
+        let json = "{\n  \"domain\": \"test\",\n  \"descriptors\": 5\n}";
+        let err = load_config_from_json(json).unwrap_err().to_string();
+        assert!(err.contains("Failed to parse JSON at line 3, column 18: invalid type"), "{}", err);
+
+        let toml = "domain = \"test\"\ndescriptors = [{ key = 1 }]\n";
+        let err = load_config_from_toml(toml).unwrap_err().to_string();
+        assert!(err.contains("Failed to parse TOML at line 2, column 24: invalid type"), "{}", err);
+    }
+}
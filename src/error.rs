@@ -31,5 +31,5 @@ pub enum RateLimitError {
     Serialization(#[from] serde_json::Error),
 
     #[error("gRPC error: {0}")]
-    Grpc(#[from] tonic::Status),
+    Grpc(#[from] Box<tonic::Status>),
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+use serde::Serialize;
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::time::Instant;
+use tonic::server::NamedService;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+use crate::{error::Result, service::RateLimitService, shutdown::Shutdown};
+
+/// How often the gRPC health status is re-evaluated
+pub const GRPC_HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Result of checking one dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyHealth {
+    pub name: String,
+    pub healthy: bool,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DependencyHealth {
+    /// A dependency that is healthy without needing a round trip
+    pub fn up(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            healthy: true,
+            latency_ms: 0.0,
+            error: None,
+        }
+    }
+
+    /// A dependency that is known to be unhealthy
+    pub fn down(name: impl Into<String>, error: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            healthy: false,
+            latency_ms: 0.0,
+            error: Some(error.into()),
+        }
+    }
+
+    /// Run a check, timing it and failing it once `timeout` has passed
+    pub async fn check<F>(name: impl Into<String>, timeout: Duration, check: F) -> Self
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let started = Instant::now();
+        let result = tokio::time::timeout(timeout, check).await;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let error = match result {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("timed out after {}ms", timeout.as_millis())),
+        };
+        Self {
+            name: name.into(),
+            healthy: error.is_none(),
+            latency_ms,
+            error,
+        }
+    }
+}
+
+/// Body of the readiness endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub draining: bool,
+    pub dependencies: Vec<DependencyHealth>,
+}
+
+impl ReadinessReport {
+    /// Ready when not draining and every dependency is healthy
+    pub fn new(draining: bool, dependencies: Vec<DependencyHealth>) -> Self {
+        Self {
+            ready: !draining && dependencies.iter().all(|dependency| dependency.healthy),
+            draining,
+            dependencies,
+        }
+    }
+}
+
+/// Keep the `grpc.health.v1.Health` status of `S` and of the whole server (`""`)
+/// in line with readiness. Reports NOT_SERVING as soon as draining begins.
+pub async fn report_grpc_health<S: NamedService>(
+    mut reporter: HealthReporter,
+    service: Arc<RateLimitService>,
+    shutdown: Shutdown,
+    interval: Duration,
+) {
+    loop {
+        // Read readiness once per tick, skipping the dependency round trips once
+        // draining so the flip is immediate
+        let draining = !shutdown.is_ready();
+        let healthy = !draining && ReadinessReport::new(false, service.dependency_health().await).ready;
+        let status = if healthy {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+        reporter.set_service_status(S::NAME, status).await;
+        reporter.set_service_status("", status).await;
+
+        if draining {
+            return;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.draining() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RateLimitError;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dependency_check_outcomes() {
+        let timeout = Duration::from_secs(1);
+
+        let ok = DependencyHealth::check("ok", timeout, async { Ok(()) }).await;
+        assert!(ok.healthy);
+        assert!(ok.error.is_none());
+
+        let failed = DependencyHealth::check("failed", timeout, async {
+            Err(RateLimitError::Service("connection refused".to_string()))
+        })
+        .await;
+        assert!(!failed.healthy);
+        assert!(failed.error.unwrap().contains("connection refused"));
+
+        let hung = DependencyHealth::check("hung", timeout, std::future::pending()).await;
+        assert!(!hung.healthy);
+        assert_eq!(hung.error.as_deref(), Some("timed out after 1000ms"));
+        assert!(hung.latency_ms >= 1000.0);
+    }
+
+    #[test]
+    fn test_readiness_report() {
+        let up = || DependencyHealth::up("config");
+        assert!(ReadinessReport::new(false, vec![up()]).ready);
+        assert!(!ReadinessReport::new(true, vec![up()]).ready);
+        assert!(!ReadinessReport::new(false, vec![up(), DependencyHealth::down("redis_primary", "down")]).ready);
+    }
+}
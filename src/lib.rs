@@ -4,15 +4,23 @@
 //! This service provides generic rate limiting capabilities for applications
 //! using domain-based configuration and descriptor matching.
 
+pub mod admin;
+pub mod audit;
 pub mod cache;
 pub mod config;
 pub mod error;
+pub mod health;
 pub mod limiter;
 pub mod metrics;
 pub mod proto;
+pub mod provider;
 pub mod redis;
 pub mod service;
+pub mod settings;
+pub mod shutdown;
+pub mod telemetry;
 pub mod utils;
+pub mod xds;
 
 // Re-export main types
 pub use cache::RateLimitCache;
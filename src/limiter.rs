@@ -1,14 +1,21 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
+use tracing::field::Empty;
 use crate::{
-    cache::{DescriptorStatus, RateLimitCache, RateLimitRequest, ResponseCode},
-    config::CompiledRateLimitConfig,
+    audit::AuditLog,
+    cache::{CounterStatus, DescriptorStatus, RateLimitCache, RateLimitRequest, ResponseCode},
+    config::{CompiledRateLimit, CompiledRateLimitConfig},
     error::{Result, RateLimitError},
+    health::DependencyHealth,
 };
 
+/// Metric label for descriptors that match no configured rule
+pub const NO_RULE_MATCHED: &str = "no_rule_matched";
+
 /// Main rate limiter that coordinates configuration and caching
 pub struct RateLimiter {
     configurations: HashMap<String, CompiledRateLimitConfig>,
     cache: Box<dyn RateLimitCache>,
+    audit: Option<Arc<AuditLog>>,
 }
 
 impl RateLimiter {
@@ -17,9 +24,22 @@ impl RateLimiter {
         Self {
             configurations: HashMap::new(),
             cache,
+            audit: None,
         }
     }
 
+    /// Log over-limit and shadow-mode decisions, when the log writes them, and admin
+    /// config changes to the given audit log
+    pub fn with_audit_log(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// The audit log decisions and config changes are written to, if any
+    pub fn audit_log(&self) -> Option<&Arc<AuditLog>> {
+        self.audit.as_ref()
+    }
+
     /// Add a configuration for a domain
     pub fn add_config(&mut self, config: CompiledRateLimitConfig) {
         let domain = config.domain().to_string();
@@ -36,7 +56,44 @@ impl RateLimiter {
         self.configurations.get(domain)
     }
 
+    /// All loaded configurations, ordered by domain
+    pub fn configs(&self) -> Vec<&CompiledRateLimitConfig> {
+        let mut configs: Vec<_> = self.configurations.values().collect();
+        configs.sort_by(|a, b| a.domain().cmp(b.domain()));
+        configs
+    }
+
+    /// Current-window counters of the limits a descriptor matches
+    pub async fn counters(&self, domain: &str, entries: &[(String, String)]) -> Result<Vec<CounterStatus>> {
+        let limits = self.matched_limits(domain, entries)?;
+        self.cache.counters(domain, entries, &limits).await
+    }
+
+    /// Set the current-window counters of the limits a descriptor matches; 0 resets them
+    pub async fn set_counters(
+        &self,
+        domain: &str,
+        entries: &[(String, String)],
+        count: u64,
+    ) -> Result<Vec<CounterStatus>> {
+        let limits = self.matched_limits(domain, entries)?;
+        self.cache.set_counters(domain, entries, &limits, count).await
+    }
+
+    fn matched_limits(&self, domain: &str, entries: &[(String, String)]) -> Result<Vec<CompiledRateLimit>> {
+        let config = self
+            .get_config(domain)
+            .ok_or_else(|| RateLimitError::DomainNotFound(domain.to_string()))?;
+        let pairs: Vec<(&str, &str)> = entries.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        Ok(config.find_limits(&pairs).to_vec())
+    }
+
     /// Check if rate limiting should be applied to the request
+    #[tracing::instrument(
+        name = "ratelimit.should_rate_limit",
+        skip_all,
+        fields(domain = %request.domain, decision = Empty)
+    )]
     pub async fn should_rate_limit(&self, request: &RateLimitRequest) -> Result<RateLimitResponse> {
         // Validate request
         if request.domain.is_empty() {
@@ -71,37 +128,105 @@ impl RateLimiter {
                 .map(|(k, v)| (k.as_str(), v.as_str()))
                 .collect();
 
-            let limit = config.find_limit(&descriptor_pairs);
+            // The lookup cannot tell which limit will decide, so it records every match
+            let span = tracing::info_span!("ratelimit.config_lookup", rules = Empty);
+            let limits = span.in_scope(|| config.find_limits(&descriptor_pairs));
+            span.record("rules", Self::matched_rules(limits).as_str());
             
             enriched_request.descriptors.push(EnrichedDescriptor {
                 entries: descriptor.entries.clone(),
-                limit: limit.cloned(),
+                limits: limits.to_vec(),
             });
         }
 
         // Delegate to cache for actual rate limiting
         let statuses = self.do_limit_with_config(&enriched_request).await?;
 
+        let metric_keys = enriched_request
+            .descriptors
+            .iter()
+            .zip(&statuses)
+            .map(|(descriptor, status)| Self::metric_key(descriptor, status))
+            .collect();
+
+        if let Some(audit) = &self.audit {
+            for (descriptor, status) in enriched_request.descriptors.iter().zip(&statuses) {
+                audit.record(&request.domain, &descriptor.entries, Self::rule_label(descriptor, status), status);
+            }
+        }
+
         // Determine overall response code
         let overall_code = if statuses.iter().any(|s| s.code == ResponseCode::OverLimit) {
             ResponseCode::OverLimit
         } else {
             ResponseCode::Ok
         };
+        tracing::Span::current().record(
+            "decision",
+            match overall_code {
+                ResponseCode::Ok => "OK",
+                ResponseCode::OverLimit => "OVER_LIMIT",
+            },
+        );
 
         Ok(RateLimitResponse {
             overall_code,
             statuses,
+            metric_keys,
         })
     }
 
+    /// Name of the limit a descriptor's status reports, falling back to the matched
+    /// config path
+    fn rule_label<'a>(descriptor: &'a EnrichedDescriptor, status: &'a DescriptorStatus) -> &'a str {
+        let Some(limit) = descriptor.limits.first() else {
+            return NO_RULE_MATCHED;
+        };
+        status
+            .current_limit
+            .as_ref()
+            .and_then(|current| current.name.as_deref())
+            .unwrap_or(&limit.path)
+    }
+
+    /// Names of every limit a descriptor matched, falling back to their config path
+    fn matched_rules(limits: &[CompiledRateLimit]) -> String {
+        if limits.is_empty() {
+            return NO_RULE_MATCHED.to_string();
+        }
+        limits
+            .iter()
+            .map(|limit| limit.name.as_deref().unwrap_or(&limit.path))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Metric label for a descriptor: the full request path and values when the
+    /// matched rule uses `detailed_metric`, otherwise the name of the reported
+    /// limit, falling back to the matched config path. Unmatched descriptors
+    /// share the `no_rule_matched` label
+    fn metric_key(descriptor: &EnrichedDescriptor, status: &DescriptorStatus) -> String {
+        let Some(limit) = descriptor.limits.first() else {
+            return NO_RULE_MATCHED.to_string();
+        };
+
+        if limit.detailed_metric {
+            return descriptor
+                .entries
+                .iter()
+                .map(|(key, value)| format!("{}_{}", key, value))
+                .collect::<Vec<_>>()
+                .join(":");
+        }
+
+        Self::rule_label(descriptor, status).to_string()
+    }
+
     /// Perform rate limiting with configuration context
     async fn do_limit_with_config(
         &self,
         request: &EnrichedRateLimitRequest,
     ) -> Result<Vec<DescriptorStatus>> {
-        // This is a simplified implementation
-        // In a complete implementation, we would pass the limits to the cache
         let base_request = RateLimitRequest {
             domain: request.domain.clone(),
             descriptors: request
@@ -114,13 +239,28 @@ impl RateLimiter {
             hits_addend: request.hits_addend,
         };
 
-        self.cache.do_limit(&base_request).await
+        let limits: Vec<_> = request.descriptors.iter().map(|d| d.limits.clone()).collect();
+
+        self.cache.do_limit(&base_request, &limits).await
     }
 
     /// Health check for the limiter
     pub async fn health_check(&self) -> Result<()> {
         self.cache.health_check().await
     }
+
+    /// Health of the loaded configuration and each cache backend
+    pub async fn dependency_health(&self) -> Vec<DependencyHealth> {
+        let config = if self.configurations.is_empty() {
+            DependencyHealth::down("config", "no rate limit configuration loaded")
+        } else {
+            DependencyHealth::up("config")
+        };
+
+        let mut dependencies = vec![config];
+        dependencies.extend(self.cache.dependency_health().await);
+        dependencies
+    }
 }
 
 /// Response for a rate limit check
@@ -128,6 +268,8 @@ impl RateLimiter {
 pub struct RateLimitResponse {
     pub overall_code: ResponseCode,
     pub statuses: Vec<DescriptorStatus>,
+    /// Metric label for each descriptor, parallel to `statuses`
+    pub metric_keys: Vec<String>,
 }
 
 /// Internal enriched request with resolved limits
@@ -137,10 +279,10 @@ struct EnrichedRateLimitRequest {
     pub hits_addend: u32,
 }
 
-/// Internal enriched descriptor with resolved limit
+/// Internal enriched descriptor with resolved limits
 struct EnrichedDescriptor {
     pub entries: Vec<(String, String)>,
-    pub limit: Option<crate::config::CompiledRateLimit>,
+    pub limits: Vec<crate::config::CompiledRateLimit>,
 }
 
 #[cfg(test)]
@@ -148,9 +290,8 @@ mod tests {
     use super::*;
     use crate::{
         cache::{RedisRateLimitCache, RateLimitDescriptor},
-        config::{CompiledRateLimit, CompiledRateLimitConfig, RateLimit, RateLimitConfig, RateLimitUnit},
+        config::{CompiledRateLimitConfig, RateLimit, RateLimitConfig, RateLimitUnit},
         redis::{RedisClientPool, RedisConfig},
-        utils::Unit,
     };
 
     async fn create_test_limiter() -> RateLimiter {
@@ -175,12 +316,21 @@ mod tests {
             descriptors: vec![crate::config::RateLimitDescriptor {
                 key: "key1".to_string(),
                 value: Some("value1".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 100,
                     unit: RateLimitUnit::Second,
+                    unit_multiplier: None,
+                    calendar: None,
+                    timezone: None,
                     unlimited: None,
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: None,
             }],
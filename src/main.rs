@@ -1,112 +1,194 @@
 use anyhow::Result;
-use axum::{extract::State, http::StatusCode, response::Json, routing::get, Router};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
 use prometheus::TextEncoder;
 use serde_json::json;
 use std::{net::SocketAddr, sync::Arc};
-use tokio::{net::TcpListener, signal};
+use tokio::net::TcpListener;
 use tonic::transport::Server;
-use tracing::{info, warn};
+use tracing::{info, warn, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use rust_ratelimit::{
+    admin::{Admin, AdminConfig},
+    audit::{AuditConfig, AuditLog},
     cache::RedisRateLimitCache,
-    config::{load_config_from_file, CompiledRateLimitConfig},
     error::RateLimitError,
+    health::{self, ReadinessReport, GRPC_HEALTH_INTERVAL},
     limiter::RateLimiter,
-    metrics::Metrics,
+    metrics::{Metrics, MetricsConfig},
+    provider::{ConfigProvider, ConfigSources, DirectoryConfigProvider, FileConfigProvider},
     proto::{RateLimitServiceServer, RateLimitRequest, RateLimitResponse},
     redis::{RedisClientPool, RedisConfig},
-    service::RateLimitService,
+    service::{GrpcRateLimitRequest, RateLimitService},
+    settings::Settings,
+    shutdown::{self, Shutdown},
+    telemetry::{self, TracingConfig},
+    xds::{XdsConfig, XdsConfigProvider},
 };
 
 #[derive(Clone)]
 struct AppState {
     service: Arc<RateLimitService>,
     metrics: Arc<Metrics>,
+    shutdown: Shutdown,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
+    let settings = Settings::load()?;
+
+    // Initialize tracing, exporting spans over OTLP when an endpoint is configured
+    let tracer = telemetry::init_tracer(&TracingConfig::from_settings(&settings))?;
+    let otel_layer = tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "rust_ratelimit=debug,tower_http=debug".into()),
         )
         .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
         .init();
 
     info!("Starting Rust Rate Limit Service");
+    info!("Loaded {:?}", settings);
 
     // Initialize components
     info!("Initializing components:");
-    let metrics = Arc::new(Metrics::new()?);
-    info!("Metrics initialized");
-    let service = create_service(metrics.clone()).await?;
+    let metrics_config = MetricsConfig::from_settings(&settings);
+    let metrics = Arc::new(Metrics::from_config(&metrics_config)?);
+    info!("Metrics initialized with {:?} backend", metrics_config.backend);
+    let service = create_service(&settings, metrics.clone()).await?;
     info!("Rate Limit Service created");
-    let state: AppState = AppState { service, metrics };
+    let shutdown = Shutdown::new();
+    let drain_period = settings.shutdown_drain();
+    let shutdown_timeout = settings.shutdown_timeout();
+    let state: AppState = AppState {
+        service,
+        metrics,
+        shutdown: shutdown.clone(),
+    };
+
+    // Configs from CONFIG_PATH take precedence over the management server's;
+    // a directory is watched for changes
+    let mut providers: Vec<Box<dyn ConfigProvider>> = Vec::new();
+    if let Some(config_path) = &settings.config_path {
+        info!("Loading configuration from: {}", config_path.display());
+        if config_path.is_dir() {
+            providers.push(Box::new(
+                DirectoryConfigProvider::new(config_path).with_poll_interval(settings.config_poll_interval()),
+            ));
+        } else {
+            providers.push(Box::new(FileConfigProvider::new(config_path)));
+        }
+    }
 
-    // Load initial configuration if provided
-    if let Ok(config_path) = std::env::var("CONFIG_PATH") {
-        load_and_add_config(&state, &config_path).await?;
+    // Subscribe to a management server for centrally managed configs
+    if let Some(xds_config) = XdsConfig::from_settings(&settings) {
+        info!("Subscribing to xDS configs at {}", xds_config.server_url);
+        providers.push(Box::new(XdsConfigProvider::new(xds_config)));
     }
-    info!("Configuration loaded successfully");
+    let sources = ConfigSources::new(state.service.clone(), state.metrics.clone());
+    sources.start(providers, &shutdown).await?;
+    info!("Config providers started");
+
+    // The HTTP and gRPC admin endpoints share one admin, so their changes don't interleave
+    let admin = Arc::new(Admin::new(state.service.clone(), &AdminConfig::from_settings(&settings)).with_config_sources(sources));
 
     // Start HTTP server for health checks and metrics
-    let http_addr = std::env::var("HTTP_ADDR")
-        .unwrap_or_else(|_| "0.0.0.0:8080".to_string())
-        .parse::<SocketAddr>()?;
+    let http_addr = settings.http_addr;
     info!("HTTP address: {}", http_addr);
-    let http_server = start_http_server(state.clone(), http_addr);
+    let mut http_server = tokio::spawn(start_http_server(state.clone(), admin.clone(), http_addr));
     info!("HTTP server started");
     // Start gRPC server
-    let grpc_addr = std::env::var("GRPC_ADDR")
-        .unwrap_or_else(|_| "0.0.0.0:8081".to_string())
-        .parse::<SocketAddr>()?;
+    let grpc_addr = settings.grpc_addr;
     info!("gRPC address: {}", grpc_addr);
-    let grpc_server = start_grpc_server(state.service.clone(), grpc_addr);
+    let mut grpc_server = tokio::spawn(start_grpc_server(
+        state.service.clone(),
+        admin,
+        grpc_addr,
+        shutdown.clone(),
+    ));
     info!("gRPC server started");
 
-    // Wait for shutdown signal
+    // Wait for a shutdown signal, or for either server to fail
+    let mut http_done = false;
+    let mut grpc_done = false;
     tokio::select! {
-        result = http_server => {
-            if let Err(e) = result {
-                warn!("HTTP server error: {}", e);
-            }
+        _ = shutdown::wait_for_signal() => {
+            shutdown.drain(drain_period).await;
         }
-        result = grpc_server => {
-            if let Err(e) = result {
-                warn!("gRPC server error: {}", e);
-            }
+        result = &mut http_server => {
+            http_done = true;
+            log_server_exit("HTTP", result);
+        }
+        result = &mut grpc_server => {
+            grpc_done = true;
+            log_server_exit("gRPC", result);
+        }
+    }
+
+    // Let in-flight requests finish before exiting, for at most the shutdown timeout
+    shutdown.stop();
+    let servers_stopped = async {
+        if !http_done {
+            log_server_exit("HTTP", (&mut http_server).await);
+        }
+        if !grpc_done {
+            log_server_exit("gRPC", (&mut grpc_server).await);
         }
-        _ = signal::ctrl_c() => {
-            info!("Received Ctrl+C, shutting down");
+    };
+    if tokio::time::timeout(shutdown_timeout, servers_stopped).await.is_err() {
+        warn!(
+            "Connections still open after {}s, closing them",
+            shutdown_timeout.as_secs()
+        );
+        http_server.abort();
+        grpc_server.abort();
+    }
+
+    // Write any queued audit events
+    if let Some(audit) = state.service.limiter().await.audit_log().cloned() {
+        if let Err(e) = tokio::task::spawn_blocking(move || audit.flush()).await {
+            warn!("Failed to flush audit log: {}", e);
         }
     }
 
+    // Push anything the metrics backend has buffered
+    let metrics = state.metrics.clone();
+    match tokio::task::spawn_blocking(move || metrics.flush()).await {
+        Ok(Err(e)) => warn!("Failed to flush metrics: {}", e),
+        Err(e) => warn!("Failed to flush metrics: {}", e),
+        Ok(Ok(())) => {}
+    }
+
+    telemetry::shutdown_tracer();
     info!("Service stopped");
     Ok(())
 }
 
-async fn create_service(metrics: Arc<Metrics>) -> Result<Arc<RateLimitService>> {
+async fn create_service(settings: &Settings, metrics: Arc<Metrics>) -> Result<Arc<RateLimitService>> {
     info!("Starting service creation...");
     
     // Configure Redis
-    let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
-    info!("Using Redis URL: {}", redis_url);
-    
     let redis_config: RedisConfig = RedisConfig {
-        url: redis_url,
+        url: settings.redis_url.clone(),
         ..Default::default()
     };
     info!("Redis config created with defaults");
 
     // Check if per-second Redis is configured
     info!("Checking for per-second Redis configuration...");
-    let redis_pool = if let Ok(per_second_url) = std::env::var("REDIS_PERSECOND_URL") {
-        info!("Found per-second Redis URL: {}, creating dual pool...", per_second_url);
+    let redis_pool = if let Some(per_second_url) = &settings.redis_persecond_url {
+        info!("Found per-second Redis URL, creating dual pool...");
         let per_second_config = RedisConfig {
-            url: per_second_url,
+            url: per_second_url.clone(),
             ..Default::default()
         };
         
@@ -138,74 +220,94 @@ async fn create_service(metrics: Arc<Metrics>) -> Result<Arc<RateLimitService>>
     info!("Redis pool creation completed, setting up cache configuration...");
 
     // Create cache
-    let local_cache_size = std::env::var("LOCAL_CACHE_SIZE")
-        .unwrap_or_else(|_| (128 * 1024 * 1024).to_string())
-        .parse::<u64>()
-        .unwrap_or(128 * 1024 * 1024u64);
-
-    let near_limit_ratio = std::env::var("NEAR_LIMIT_RATIO")
-        .unwrap_or_else(|_| "0.8".to_string())
-        .parse::<f32>()
-        .unwrap_or(0.8);
-
-    let cache_key_prefix = std::env::var("CACHE_KEY_PREFIX").unwrap_or_default();
-
     info!("Creating rate limit cache with size: {}, ratio: {}, prefix: '{}'", 
-           local_cache_size, near_limit_ratio, cache_key_prefix);
+           settings.local_cache_size, settings.near_limit_ratio, settings.cache_key_prefix);
 
     let cache = RedisRateLimitCache::new(
         redis_pool,
-        local_cache_size,
-        near_limit_ratio,
-        cache_key_prefix,
-    );
+        settings.local_cache_size,
+        settings.near_limit_ratio,
+        settings.cache_key_prefix.clone(),
+    )
+    .with_metrics(metrics.clone());
 
     info!("Cache created, setting up limiter and service...");
 
     // Create limiter and service
     let limiter = RateLimiter::new(Box::new(cache));
+    // Config changes are always audited; decisions only when the audit log is enabled
+    let audit_config = AuditConfig::from_settings(settings);
+    info!(
+        "Audit log writing to {:?} (decisions: {})",
+        audit_config.path, audit_config.decisions
+    );
+    let audit = AuditLog::new(audit_config)?.with_metrics(metrics.clone());
+    let limiter = limiter.with_audit_log(Arc::new(audit));
     let service = Arc::new(RateLimitService::new(limiter, metrics));
 
     info!("Service creation completed successfully");
     Ok(service)
 }
 
-async fn load_and_add_config(state: &AppState, config_path: &str) -> Result<()> {
-    info!("Loading configuration from: {}", config_path);
-    
-    let config = load_config_from_file(config_path)?;
-    let compiled_config = CompiledRateLimitConfig::compile(config)?;
-    
-    state.service.add_config(compiled_config).await?;
-    
-    info!("Configuration loaded successfully");
-    Ok(())
+fn log_server_exit(name: &str, result: std::result::Result<Result<()>, tokio::task::JoinError>) {
+    match result {
+        Ok(Ok(())) => info!("{} server stopped", name),
+        Ok(Err(e)) => warn!("{} server error: {}", name, e),
+        Err(e) => warn!("{} server task failed: {}", name, e),
+    }
 }
 
-async fn start_http_server(state: AppState, addr: SocketAddr) -> Result<()> {
+async fn start_http_server(state: AppState, admin: Arc<Admin>, addr: SocketAddr) -> Result<()> {
+    let shutdown = state.shutdown.clone();
     let app: Router = Router::new()
         .route("/healthcheck", get(health_check))
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .route("/json", post(json_handler))
         .route("/metrics", get(metrics_handler))
-        .with_state(state);
+        .with_state(state)
+        .merge(admin.router());
 
     let listener: TcpListener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.stopped().await })
+        .await?;
     
     Ok(())
 }
 
-async fn start_grpc_server(service: Arc<RateLimitService>, addr: SocketAddr) -> Result<()> {
+async fn start_grpc_server(
+    service: Arc<RateLimitService>,
+    admin: Arc<Admin>,
+    addr: SocketAddr,
+    shutdown: Shutdown,
+) -> Result<()> {
     info!("Starting gRPC server with tonic at {}", addr);
     
     // Create the gRPC service implementation using the generated protobuf types
     let grpc_service = RateLimitServiceImpl {
-        rate_limit_service: service,
+        rate_limit_service: service.clone(),
     };
     
+    // Report grpc.health.v1 status from the same checks as /readyz
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(health::report_grpc_health::<RateLimitServiceServer<RateLimitServiceImpl>>(
+        health_reporter,
+        service.clone(),
+        shutdown.clone(),
+        GRPC_HEALTH_INTERVAL,
+    ));
+
+    // Let grpcurl and similar tools discover the API without proto files
+    let reflection_service = rust_ratelimit::proto::reflection_service()?;
+
     // Start the real tonic gRPC server with generated protobuf support
     Server::builder()
+        .add_service(health_service)
+        .add_service(reflection_service)
         .add_service(RateLimitServiceServer::new(grpc_service))
-        .serve(addr)
+        .add_optional_service(admin.grpc_service())
+        .serve_with_shutdown(addr, async move { shutdown.stopped().await })
         .await
         .map_err(|e| anyhow::anyhow!("gRPC server error: {}", e))?;
     
@@ -224,6 +326,15 @@ impl rust_ratelimit::proto::RateLimitService for RateLimitServiceImpl {
         &self,
         request: tonic::Request<RateLimitRequest>,
     ) -> Result<tonic::Response<RateLimitResponse>, tonic::Status> {
+        // Continue the caller's trace when it propagates W3C trace context
+        let span = tracing::info_span!(
+            "ratelimit.grpc.ShouldRateLimit",
+            otel.kind = "server",
+            rpc.system = "grpc",
+            domain = %request.get_ref().domain,
+        );
+        span.set_parent(telemetry::extract_context(request.metadata()));
+
         let req = request.into_inner();
         
         // Convert protobuf request to internal request format
@@ -243,7 +354,12 @@ impl rust_ratelimit::proto::RateLimitService for RateLimitServiceImpl {
         };
         
         // Call our rate limit service
-        match self.rate_limit_service.should_rate_limit_direct(internal_request).await {
+        match self
+            .rate_limit_service
+            .should_rate_limit_direct(internal_request)
+            .instrument(span)
+            .await
+        {
             Ok(response) => {
                 // Convert internal response to protobuf response
                 let grpc_response = RateLimitResponse {
@@ -288,6 +404,11 @@ impl rust_ratelimit::proto::RateLimitService for RateLimitServiceImpl {
 }
 
 async fn health_check(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    // Fail while draining so load balancers stop sending traffic
+    if !state.shutdown.is_ready() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     match state.service.health_check().await {
         Ok(()) => Ok(Json(json!({
             "status": "healthy",
@@ -297,6 +418,51 @@ async fn health_check(State(state): State<AppState>) -> Result<Json<serde_json::
     }
 }
 
+/// Liveness only reflects the process, so a Redis outage does not restart the pod
+async fn livez() -> Json<serde_json::Value> {
+    Json(json!({ "status": "alive" }))
+}
+
+/// Ready when configuration is loaded, every Redis client answers and the service is not draining
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadinessReport>) {
+    let report = ReadinessReport::new(
+        !state.shutdown.is_ready(),
+        state.service.dependency_health().await,
+    );
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+/// Rate limit check for clients that cannot speak gRPC; answers 429 when over limit
+async fn json_handler(
+    State(state): State<AppState>,
+    Json(request): Json<GrpcRateLimitRequest>,
+) -> Response {
+    match state.service.should_rate_limit_direct(request).await {
+        Ok(response) => {
+            let status = if response.is_over_limit() {
+                StatusCode::TOO_MANY_REQUESTS
+            } else {
+                StatusCode::OK
+            };
+            (status, Json(response)).into_response()
+        }
+        Err(e) => {
+            let status = match e {
+                RateLimitError::DomainNotFound(_) => StatusCode::NOT_FOUND,
+                RateLimitError::Service(_) => StatusCode::BAD_REQUEST,
+                RateLimitError::Redis(_) => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (status, Json(json!({ "error": e.to_string() }))).into_response()
+        }
+    }
+}
+
 async fn metrics_handler(State(state): State<AppState>) -> Result<String, StatusCode> {
     let encoder = TextEncoder::new();
     let metric_families = state.metrics.registry().gather();
@@ -1,160 +1,399 @@
-use prometheus::{
-    Counter, CounterVec, GaugeVec, Histogram, HistogramOpts, HistogramVec, Opts, Registry,
+use prometheus::{CounterVec, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
-use std::sync::Arc;
+
+use crate::{
+    cache::HitCounts,
+    error::{RateLimitError, Result},
+    settings::Settings,
+};
+
+mod otlp;
+mod statsd;
+
+pub use otlp::OtlpSink;
+pub use statsd::{StatsdFormat, StatsdSink};
+
+/// Default cap on distinct `(domain, descriptor)` label sets
+pub const DEFAULT_MAX_DESCRIPTOR_SERIES: usize = 1000;
+
+/// Port StatsD is sent to when only a host is configured
+pub const DEFAULT_STATSD_PORT: u16 = 8125;
+
+/// Descriptor label used once the cardinality cap is reached
+pub const OVERFLOW_DESCRIPTOR: &str = "_other";
+
+/// Kind of a metric
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// Metric name, help text and label names, shared by every backend
+#[derive(Debug)]
+pub struct MetricDef {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub labels: &'static [&'static str],
+}
+
+const DESCRIPTOR_LABELS: &[&str] = &["domain", "descriptor"];
+
+pub const TOTAL_REQUESTS: MetricDef = MetricDef {
+    name: "ratelimit_total_requests",
+    help: "Total number of rate limit requests",
+    kind: MetricKind::Counter,
+    labels: DESCRIPTOR_LABELS,
+};
+
+pub const OVER_LIMIT_REQUESTS: MetricDef = MetricDef {
+    name: "ratelimit_over_limit_requests",
+    help: "Number of requests that exceeded rate limits",
+    kind: MetricKind::Counter,
+    labels: DESCRIPTOR_LABELS,
+};
+
+pub const NEAR_LIMIT_REQUESTS: MetricDef = MetricDef {
+    name: "ratelimit_near_limit_requests",
+    help: "Number of requests that are near the rate limit threshold",
+    kind: MetricKind::Counter,
+    labels: DESCRIPTOR_LABELS,
+};
+
+pub const WITHIN_LIMIT_REQUESTS: MetricDef = MetricDef {
+    name: "ratelimit_within_limit_requests",
+    help: "Number of requests that are within rate limits",
+    kind: MetricKind::Counter,
+    labels: DESCRIPTOR_LABELS,
+};
+
+pub const SHADOW_MODE_REQUESTS: MetricDef = MetricDef {
+    name: "ratelimit_shadow_mode_requests",
+    help: "Number of requests processed in shadow mode",
+    kind: MetricKind::Counter,
+    labels: DESCRIPTOR_LABELS,
+};
+
+pub const DROPPED_DESCRIPTOR_SERIES: MetricDef = MetricDef {
+    name: "ratelimit_descriptor_series_dropped",
+    help: "Number of descriptor updates recorded under the _other series because the label set cap was reached; one per request descriptor, however many hits it adds",
+    kind: MetricKind::Counter,
+    labels: &[],
+};
+
+pub const AUDIT_EVENTS_DROPPED: MetricDef = MetricDef {
+    name: "ratelimit_audit_events_dropped",
+    help: "Number of audit events dropped because the audit log queue was full",
+    kind: MetricKind::Counter,
+    labels: &[],
+};
+
+pub const LOCAL_CACHE_HITS: MetricDef = MetricDef {
+    name: "ratelimit_local_cache_hits",
+    help: "Number of local cache hits",
+    kind: MetricKind::Counter,
+    labels: &[],
+};
+
+pub const LOCAL_CACHE_MISSES: MetricDef = MetricDef {
+    name: "ratelimit_local_cache_misses",
+    help: "Number of local cache misses",
+    kind: MetricKind::Counter,
+    labels: &[],
+};
+
+pub const REDIS_OPERATIONS: MetricDef = MetricDef {
+    name: "ratelimit_redis_operations",
+    help: "Number of Redis operations by type",
+    kind: MetricKind::Counter,
+    labels: &["operation", "result"],
+};
+
+pub const REDIS_OPERATION_DURATION: MetricDef = MetricDef {
+    name: "ratelimit_redis_operation_duration_seconds",
+    help: "Duration of Redis operations in seconds",
+    kind: MetricKind::Histogram,
+    labels: &["operation"],
+};
+
+pub const REDIS_CONNECTIONS_ACTIVE: MetricDef = MetricDef {
+    name: "ratelimit_redis_connections_active",
+    help: "Number of active Redis connections",
+    kind: MetricKind::Gauge,
+    labels: &["instance"],
+};
+
+pub const CONFIG_LOAD_SUCCESS: MetricDef = MetricDef {
+    name: "ratelimit_config_load_success",
+    help: "Number of successful configuration loads",
+    kind: MetricKind::Counter,
+    labels: &[],
+};
+
+pub const CONFIG_LOAD_ERROR: MetricDef = MetricDef {
+    name: "ratelimit_config_load_error",
+    help: "Number of failed configuration loads",
+    kind: MetricKind::Counter,
+    labels: &[],
+};
+
+pub const REQUEST_DURATION: MetricDef = MetricDef {
+    name: "ratelimit_request_duration_seconds",
+    help: "Duration of rate limit requests in seconds",
+    kind: MetricKind::Histogram,
+    labels: &[],
+};
+
+/// Every metric recorded by the service
+pub const ALL_METRICS: &[&MetricDef] = &[
+    &TOTAL_REQUESTS,
+    &OVER_LIMIT_REQUESTS,
+    &NEAR_LIMIT_REQUESTS,
+    &WITHIN_LIMIT_REQUESTS,
+    &SHADOW_MODE_REQUESTS,
+    &DROPPED_DESCRIPTOR_SERIES,
+    &AUDIT_EVENTS_DROPPED,
+    &LOCAL_CACHE_HITS,
+    &LOCAL_CACHE_MISSES,
+    &REDIS_OPERATIONS,
+    &REDIS_OPERATION_DURATION,
+    &REDIS_CONNECTIONS_ACTIVE,
+    &CONFIG_LOAD_SUCCESS,
+    &CONFIG_LOAD_ERROR,
+    &REQUEST_DURATION,
+];
+
+/// Backend that recorded metrics are written to
+pub trait MetricsSink: Send + Sync {
+    /// Record a value: counters add it, gauges are set to it and histograms observe it.
+    /// `labels` holds one value per name in `metric.labels`
+    fn record(&self, metric: &MetricDef, labels: &[&str], value: f64);
+
+    /// Send any buffered metrics
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Metrics backend selected by configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetricsBackend {
+    /// Prometheus registry scraped at `/metrics`
+    #[default]
+    Prometheus,
+    /// Periodic OTLP gRPC push
+    Otlp,
+    /// StatsD over UDP
+    Statsd,
+}
+
+impl FromStr for MetricsBackend {
+    type Err = RateLimitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "prometheus" => Ok(MetricsBackend::Prometheus),
+            "otlp" => Ok(MetricsBackend::Otlp),
+            "statsd" => Ok(MetricsBackend::Statsd),
+            other => Err(RateLimitError::Config(format!(
+                "unknown metrics backend '{}', expected prometheus, otlp or statsd",
+                other
+            ))),
+        }
+    }
+}
+
+/// Metrics backend configuration
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    pub backend: MetricsBackend,
+    pub max_descriptor_series: usize,
+    /// OTLP gRPC collector endpoint
+    pub otlp_endpoint: String,
+    /// How often OTLP metrics are pushed
+    pub otlp_interval: Duration,
+    /// StatsD `host:port`
+    pub statsd_address: String,
+    pub statsd_format: StatsdFormat,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            backend: MetricsBackend::Prometheus,
+            max_descriptor_series: DEFAULT_MAX_DESCRIPTOR_SERIES,
+            otlp_endpoint: "http://localhost:4317".to_string(),
+            otlp_interval: Duration::from_secs(60),
+            statsd_address: format!("localhost:{}", DEFAULT_STATSD_PORT),
+            statsd_format: StatsdFormat::Tagged,
+        }
+    }
+}
+
+impl MetricsConfig {
+    /// The metrics configuration given by the settings
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            backend: settings.metrics_backend,
+            max_descriptor_series: settings.metrics_max_descriptor_series,
+            otlp_endpoint: settings
+                .otel_exporter_otlp_metrics_endpoint
+                .clone()
+                .or_else(|| settings.otel_exporter_otlp_endpoint.clone())
+                .unwrap_or(Self::default().otlp_endpoint),
+            otlp_interval: Duration::from_secs(settings.metrics_push_interval_secs),
+            statsd_address: format!("{}:{}", settings.statsd_host, settings.statsd_port),
+            statsd_format: settings.statsd_format,
+        }
+    }
+}
+
+/// Prometheus backend, registering every metric in a registry
+pub struct PrometheusSink {
+    counters: HashMap<&'static str, CounterVec>,
+    gauges: HashMap<&'static str, GaugeVec>,
+    histograms: HashMap<&'static str, HistogramVec>,
+}
+
+impl PrometheusSink {
+    /// Create the metrics and register them in `registry`
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let mut sink = Self {
+            counters: HashMap::new(),
+            gauges: HashMap::new(),
+            histograms: HashMap::new(),
+        };
+
+        for metric in ALL_METRICS {
+            match metric.kind {
+                MetricKind::Counter => {
+                    let counter = CounterVec::new(Opts::new(metric.name, metric.help), metric.labels)?;
+                    registry.register(Box::new(counter.clone()))?;
+                    sink.counters.insert(metric.name, counter);
+                }
+                MetricKind::Gauge => {
+                    let gauge = GaugeVec::new(Opts::new(metric.name, metric.help), metric.labels)?;
+                    registry.register(Box::new(gauge.clone()))?;
+                    sink.gauges.insert(metric.name, gauge);
+                }
+                MetricKind::Histogram => {
+                    let histogram =
+                        HistogramVec::new(HistogramOpts::new(metric.name, metric.help), metric.labels)?;
+                    registry.register(Box::new(histogram.clone()))?;
+                    sink.histograms.insert(metric.name, histogram);
+                }
+            }
+        }
+
+        Ok(sink)
+    }
+}
+
+impl MetricsSink for PrometheusSink {
+    fn record(&self, metric: &MetricDef, labels: &[&str], value: f64) {
+        match metric.kind {
+            MetricKind::Counter => {
+                if let Some(counter) = self.counters.get(metric.name) {
+                    counter.with_label_values(labels).inc_by(value);
+                }
+            }
+            MetricKind::Gauge => {
+                if let Some(gauge) = self.gauges.get(metric.name) {
+                    gauge.with_label_values(labels).set(value);
+                }
+            }
+            MetricKind::Histogram => {
+                if let Some(histogram) = self.histograms.get(metric.name) {
+                    histogram.with_label_values(labels).observe(value);
+                }
+            }
+        }
+    }
+}
 
 /// Metrics collector for the rate limit service
 #[derive(Clone)]
 pub struct Metrics {
+    /// Scraped at `/metrics`; stays empty with push backends
     registry: Arc<Registry>,
-    
-    // Rate limit metrics
-    total_requests: CounterVec,
-    over_limit_requests: CounterVec,
-    near_limit_requests: CounterVec,
-    within_limit_requests: CounterVec,
-    shadow_mode_requests: CounterVec,
-    
-    // Cache metrics
-    local_cache_hits: Counter,
-    local_cache_misses: Counter,
-    
-    // Redis metrics
-    redis_operations: CounterVec,
-    redis_operation_duration: HistogramVec,
-    redis_connection_active: GaugeVec,
-    
-    // Service metrics
-    config_load_success: Counter,
-    config_load_error: Counter,
-    request_duration: Histogram,
+    sink: Arc<dyn MetricsSink>,
+
+    // Cardinality guard for descriptor labels
+    descriptor_series: Arc<Mutex<DescriptorSeries>>,
+    max_descriptor_series: usize,
 }
 
 impl Metrics {
-    /// Create a new metrics instance
+    /// Create a new metrics instance backed by a Prometheus registry
     pub fn new() -> prometheus::Result<Self> {
-        let registry = Arc::new(Registry::new());
-
-        let total_requests = CounterVec::new(
-            Opts::new(
-                "ratelimit_total_requests",
-                "Total number of rate limit requests",
-            ),
-            &["domain", "descriptor"],
-        )?;
+        let registry = Registry::new();
+        let sink = PrometheusSink::new(&registry)?;
+        Ok(Self::with_sink(Arc::new(registry), Arc::new(sink)))
+    }
 
-        let over_limit_requests = CounterVec::new(
-            Opts::new(
-                "ratelimit_over_limit_requests",
-                "Number of requests that exceeded rate limits",
+    /// Create a metrics instance for the configured backend
+    pub fn from_config(config: &MetricsConfig) -> Result<Self> {
+        let metrics = match config.backend {
+            MetricsBackend::Prometheus => Self::new().map_err(|e| {
+                RateLimitError::Service(format!("Failed to create Prometheus metrics: {}", e))
+            })?,
+            MetricsBackend::Otlp => Self::with_sink(
+                Arc::new(Registry::new()),
+                Arc::new(OtlpSink::new(&config.otlp_endpoint, config.otlp_interval)?),
             ),
-            &["domain", "descriptor"],
-        )?;
-
-        let near_limit_requests = CounterVec::new(
-            Opts::new(
-                "ratelimit_near_limit_requests",
-                "Number of requests that are near the rate limit threshold",
+            MetricsBackend::Statsd => Self::with_sink(
+                Arc::new(Registry::new()),
+                Arc::new(StatsdSink::new(&config.statsd_address, config.statsd_format)?),
             ),
-            &["domain", "descriptor"],
-        )?;
+        };
+        Ok(metrics.with_max_descriptor_series(config.max_descriptor_series))
+    }
 
-        let within_limit_requests = CounterVec::new(
-            Opts::new(
-                "ratelimit_within_limit_requests",
-                "Number of requests that are within rate limits",
-            ),
-            &["domain", "descriptor"],
-        )?;
+    /// Create a metrics instance writing to the given sink
+    pub fn with_sink(registry: Arc<Registry>, sink: Arc<dyn MetricsSink>) -> Self {
+        Self {
+            registry,
+            sink,
+            descriptor_series: Arc::new(Mutex::new(DescriptorSeries::default())),
+            max_descriptor_series: DEFAULT_MAX_DESCRIPTOR_SERIES,
+        }
+    }
 
-        let shadow_mode_requests = CounterVec::new(
-            Opts::new(
-                "ratelimit_shadow_mode_requests",
-                "Number of requests processed in shadow mode",
-            ),
-            &["domain", "descriptor"],
-        )?;
-
-        let local_cache_hits = Counter::new(
-            "ratelimit_local_cache_hits",
-            "Number of local cache hits",
-        )?;
-
-        let local_cache_misses = Counter::new(
-            "ratelimit_local_cache_misses",
-            "Number of local cache misses",
-        )?;
-
-        let redis_operations = CounterVec::new(
-            Opts::new(
-                "ratelimit_redis_operations",
-                "Number of Redis operations by type",
-            ),
-            &["operation", "result"],
-        )?;
+    /// Set the cap on distinct `(domain, descriptor)` label sets
+    pub fn with_max_descriptor_series(mut self, max: usize) -> Self {
+        self.max_descriptor_series = max;
+        self
+    }
 
-        let redis_operation_duration = HistogramVec::new(
-            HistogramOpts::new(
-                "ratelimit_redis_operation_duration_seconds",
-                "Duration of Redis operations in seconds",
-            ),
-            &["operation"],
-        )?;
+    /// Descriptor label to record under, collapsing new label sets into
+    /// `_other` once the cap is reached
+    fn descriptor_label<'a>(&self, domain: &str, descriptor: &'a str) -> &'a str {
+        let mut series = self.descriptor_series.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (domain.to_string(), descriptor.to_string());
+        if series.kept.contains(&key) {
+            return descriptor;
+        }
+        if series.kept.len() < self.max_descriptor_series {
+            series.kept.insert(key);
+            return descriptor;
+        }
+        // Count the update rather than the label set, so collapsed sets keep no state
+        drop(series);
+        self.sink.record(&DROPPED_DESCRIPTOR_SERIES, &[], 1.0);
+        OVERFLOW_DESCRIPTOR
+    }
 
-        let redis_connection_active = GaugeVec::new(
-            Opts::new(
-                "ratelimit_redis_connections_active",
-                "Number of active Redis connections",
-            ),
-            &["instance"],
-        )?;
-
-        let config_load_success = Counter::new(
-            "ratelimit_config_load_success",
-            "Number of successful configuration loads",
-        )?;
-
-        let config_load_error = Counter::new(
-            "ratelimit_config_load_error",
-            "Number of failed configuration loads",
-        )?;
-
-        let request_duration = Histogram::with_opts(HistogramOpts::new(
-            "ratelimit_request_duration_seconds",
-            "Duration of rate limit requests in seconds",
-        ))?;
-
-        // Register all metrics
-        registry.register(Box::new(total_requests.clone()))?;
-        registry.register(Box::new(over_limit_requests.clone()))?;
-        registry.register(Box::new(near_limit_requests.clone()))?;
-        registry.register(Box::new(within_limit_requests.clone()))?;
-        registry.register(Box::new(shadow_mode_requests.clone()))?;
-        registry.register(Box::new(local_cache_hits.clone()))?;
-        registry.register(Box::new(local_cache_misses.clone()))?;
-        registry.register(Box::new(redis_operations.clone()))?;
-        registry.register(Box::new(redis_operation_duration.clone()))?;
-        registry.register(Box::new(redis_connection_active.clone()))?;
-        registry.register(Box::new(config_load_success.clone()))?;
-        registry.register(Box::new(config_load_error.clone()))?;
-        registry.register(Box::new(request_duration.clone()))?;
-
-        Ok(Self {
-            registry,
-            total_requests,
-            over_limit_requests,
-            near_limit_requests,
-            within_limit_requests,
-            shadow_mode_requests,
-            local_cache_hits,
-            local_cache_misses,
-            redis_operations,
-            redis_operation_duration,
-            redis_connection_active,
-            config_load_success,
-            config_load_error,
-            request_duration,
-        })
+    /// Record a per-descriptor counter
+    fn record_descriptor(&self, metric: &MetricDef, domain: &str, descriptor: &str) {
+        let descriptor = self.descriptor_label(domain, descriptor);
+        self.sink.record(metric, &[domain, descriptor], 1.0);
     }
 
     /// Get the Prometheus registry for this metrics instance
@@ -162,76 +401,105 @@ impl Metrics {
         &self.registry
     }
 
+    /// Send any metrics buffered by the backend
+    pub fn flush(&self) -> Result<()> {
+        self.sink.flush()
+    }
+
     /// Record a total request
     pub fn record_total_request(&self, domain: &str, descriptor: &str) {
-        self.total_requests.with_label_values(&[domain, descriptor]).inc();
+        self.record_descriptor(&TOTAL_REQUESTS, domain, descriptor);
     }
 
     /// Record an over-limit request
     pub fn record_over_limit_request(&self, domain: &str, descriptor: &str) {
-        self.over_limit_requests.with_label_values(&[domain, descriptor]).inc();
+        self.record_descriptor(&OVER_LIMIT_REQUESTS, domain, descriptor);
     }
 
     /// Record a near-limit request
     pub fn record_near_limit_request(&self, domain: &str, descriptor: &str) {
-        self.near_limit_requests.with_label_values(&[domain, descriptor]).inc();
+        self.record_descriptor(&NEAR_LIMIT_REQUESTS, domain, descriptor);
     }
 
     /// Record a within-limit request
     pub fn record_within_limit_request(&self, domain: &str, descriptor: &str) {
-        self.within_limit_requests.with_label_values(&[domain, descriptor]).inc();
+        self.record_descriptor(&WITHIN_LIMIT_REQUESTS, domain, descriptor);
     }
 
     /// Record a shadow-mode request
     pub fn record_shadow_mode_request(&self, domain: &str, descriptor: &str) {
-        self.shadow_mode_requests.with_label_values(&[domain, descriptor]).inc();
+        self.record_descriptor(&SHADOW_MODE_REQUESTS, domain, descriptor);
+    }
+
+    /// Record the hits of one descriptor under every per-descriptor counter they
+    /// count towards
+    pub fn record_hits(&self, domain: &str, descriptor: &str, hits: &HitCounts) {
+        let descriptor = self.descriptor_label(domain, descriptor);
+        let counts = [
+            (&TOTAL_REQUESTS, hits.total),
+            (&OVER_LIMIT_REQUESTS, hits.over_limit),
+            (&NEAR_LIMIT_REQUESTS, hits.near_limit),
+            (&WITHIN_LIMIT_REQUESTS, hits.within_limit),
+            (&SHADOW_MODE_REQUESTS, hits.shadow_mode),
+        ];
+        for (metric, count) in counts {
+            if count > 0 {
+                self.sink.record(metric, &[domain, descriptor], count as f64);
+            }
+        }
+    }
+
+    /// Record an audit event dropped on a full queue
+    pub fn record_audit_event_dropped(&self) {
+        self.sink.record(&AUDIT_EVENTS_DROPPED, &[], 1.0);
     }
 
     /// Record a local cache hit
     pub fn record_local_cache_hit(&self) {
-        self.local_cache_hits.inc();
+        self.sink.record(&LOCAL_CACHE_HITS, &[], 1.0);
     }
 
     /// Record a local cache miss
     pub fn record_local_cache_miss(&self) {
-        self.local_cache_misses.inc();
+        self.sink.record(&LOCAL_CACHE_MISSES, &[], 1.0);
     }
 
     /// Record a Redis operation
     pub fn record_redis_operation(&self, operation: &str, result: &str) {
-        self.redis_operations.with_label_values(&[operation, result]).inc();
+        self.sink.record(&REDIS_OPERATIONS, &[operation, result], 1.0);
     }
 
     /// Record Redis operation duration
     pub fn record_redis_operation_duration(&self, operation: &str, duration_seconds: f64) {
-        self.redis_operation_duration
-            .with_label_values(&[operation])
-            .observe(duration_seconds);
+        self.sink.record(&REDIS_OPERATION_DURATION, &[operation], duration_seconds);
     }
 
     /// Set active Redis connections
     pub fn set_redis_connections_active(&self, instance: &str, count: f64) {
-        self.redis_connection_active.with_label_values(&[instance]).set(count);
+        self.sink.record(&REDIS_CONNECTIONS_ACTIVE, &[instance], count);
     }
 
     /// Record successful configuration load
     pub fn record_config_load_success(&self) {
-        self.config_load_success.inc();
+        self.sink.record(&CONFIG_LOAD_SUCCESS, &[], 1.0);
     }
 
     /// Record failed configuration load
     pub fn record_config_load_error(&self) {
-        self.config_load_error.inc();
+        self.sink.record(&CONFIG_LOAD_ERROR, &[], 1.0);
     }
 
     /// Record request duration
     pub fn record_request_duration(&self, duration_seconds: f64) {
-        self.request_duration.observe(duration_seconds);
+        self.sink.record(&REQUEST_DURATION, &[], duration_seconds);
     }
 
-    /// Create a timer for measuring request duration
-    pub fn start_request_timer(&self) -> prometheus::HistogramTimer {
-        self.request_duration.start_timer()
+    /// Create a timer for measuring request duration, recorded when dropped
+    pub fn start_request_timer(&self) -> RequestTimer {
+        RequestTimer {
+            metrics: self.clone(),
+            started: Instant::now(),
+        }
     }
 }
 
@@ -241,6 +509,32 @@ impl Default for Metrics {
     }
 }
 
+/// Label sets with their own series, at most `max_descriptor_series` of them
+#[derive(Default)]
+struct DescriptorSeries {
+    kept: HashSet<(String, String)>,
+}
+
+/// Records the request duration when dropped
+pub struct RequestTimer {
+    metrics: Metrics,
+    started: Instant,
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        self.metrics.record_request_duration(self.started.elapsed().as_secs_f64());
+    }
+}
+
+/// Attribute pairs for a metric's label values
+fn label_pairs<'a>(
+    metric: &MetricDef,
+    labels: &'a [&'a str],
+) -> impl Iterator<Item = (&'static str, &'a str)> + 'a {
+    metric.labels.iter().copied().zip(labels.iter().copied())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -276,4 +570,66 @@ mod tests {
         let total_requests_found = families.iter().any(|f| f.get_name() == "ratelimit_total_requests");
         assert!(total_requests_found);
     }
+
+    fn counter_value(metrics: &Metrics, name: &str, descriptor: Option<&str>) -> f64 {
+        metrics
+            .registry()
+            .gather()
+            .iter()
+            .filter(|f| f.get_name() == name)
+            .flat_map(|f| f.get_metric())
+            .filter(|m| {
+                descriptor.is_none_or(|d| {
+                    m.get_label().iter().any(|l| l.get_name() == "descriptor" && l.get_value() == d)
+                })
+            })
+            .map(|m| m.get_counter().get_value())
+            .sum()
+    }
+
+    #[test]
+    fn test_metrics_backend_parsing() {
+        assert_eq!("prometheus".parse::<MetricsBackend>().unwrap(), MetricsBackend::Prometheus);
+        assert_eq!("OTLP".parse::<MetricsBackend>().unwrap(), MetricsBackend::Otlp);
+        assert_eq!("statsd".parse::<MetricsBackend>().unwrap(), MetricsBackend::Statsd);
+        assert!("graphite".parse::<MetricsBackend>().is_err());
+    }
+
+    #[test]
+    fn test_record_hits_adds_each_count() {
+        let metrics = Metrics::new().unwrap();
+        let hits = HitCounts { total: 5, over_limit: 1, near_limit: 2, within_limit: 0, shadow_mode: 0 };
+        metrics.record_hits("test", "desc", &hits);
+        metrics.record_hits("test", "desc", &HitCounts::within(3));
+
+        assert_eq!(counter_value(&metrics, "ratelimit_total_requests", Some("desc")), 8.0);
+        assert_eq!(counter_value(&metrics, "ratelimit_over_limit_requests", Some("desc")), 1.0);
+        assert_eq!(counter_value(&metrics, "ratelimit_near_limit_requests", Some("desc")), 2.0);
+        assert_eq!(counter_value(&metrics, "ratelimit_within_limit_requests", Some("desc")), 3.0);
+    }
+
+    #[test]
+    fn test_descriptor_cardinality_cap() {
+        let metrics = Metrics::new().unwrap().with_max_descriptor_series(2);
+
+        metrics.record_total_request("test", "a");
+        metrics.record_total_request("test", "b");
+        metrics.record_total_request("test", "c");
+        metrics.record_within_limit_request("test", "c");
+        metrics.record_over_limit_request("test", "d");
+        metrics.record_total_request("test", "d");
+
+        // Known label sets keep their own series after the cap is reached
+        metrics.record_total_request("test", "a");
+
+        assert_eq!(counter_value(&metrics, "ratelimit_total_requests", Some("a")), 2.0);
+        assert_eq!(counter_value(&metrics, "ratelimit_total_requests", Some("b")), 1.0);
+        assert_eq!(counter_value(&metrics, "ratelimit_total_requests", Some("c")), 0.0);
+        assert_eq!(counter_value(&metrics, "ratelimit_total_requests", Some(OVERFLOW_DESCRIPTOR)), 2.0);
+        assert_eq!(counter_value(&metrics, "ratelimit_over_limit_requests", Some(OVERFLOW_DESCRIPTOR)), 1.0);
+        // Every update to a collapsed label set is counted
+        assert_eq!(counter_value(&metrics, "ratelimit_descriptor_series_dropped", None), 4.0);
+        metrics.record_hits("test", "e", &HitCounts::within(5));
+        assert_eq!(counter_value(&metrics, "ratelimit_descriptor_series_dropped", None), 5.0);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+use opentelemetry::{
+    metrics::{Counter, Histogram, MeterProvider as _, ObservableGauge},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{metrics::SdkMeterProvider, runtime};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{label_pairs, MetricDef, MetricKind, MetricsSink, ALL_METRICS};
+use crate::error::{RateLimitError, Result};
+
+/// Last value set for each gauge label set, read when the gauge is observed
+type GaugeValues = Arc<Mutex<HashMap<Vec<(&'static str, String)>, f64>>>;
+
+/// OTLP backend, pushing metrics to a collector on a fixed interval
+pub struct OtlpSink {
+    provider: SdkMeterProvider,
+    counters: HashMap<&'static str, Counter<f64>>,
+    histograms: HashMap<&'static str, Histogram<f64>>,
+    gauges: HashMap<&'static str, GaugeValues>,
+    // Gauges report through callbacks and must stay registered
+    _observable_gauges: Vec<ObservableGauge<f64>>,
+}
+
+impl OtlpSink {
+    /// Start pushing metrics to the OTLP gRPC endpoint every `interval`.
+    /// Must be called from within a Tokio runtime
+    pub fn new(endpoint: &str, interval: Duration) -> Result<Self> {
+        let provider = opentelemetry_otlp::new_pipeline()
+            .metrics(runtime::Tokio)
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_period(interval)
+            .build()
+            .map_err(|e| RateLimitError::Service(format!("Failed to create OTLP metrics exporter: {}", e)))?;
+
+        let meter = provider.meter("ratelimit");
+        let mut sink = Self {
+            provider: provider.clone(),
+            counters: HashMap::new(),
+            histograms: HashMap::new(),
+            gauges: HashMap::new(),
+            _observable_gauges: Vec::new(),
+        };
+
+        for metric in ALL_METRICS {
+            match metric.kind {
+                MetricKind::Counter => {
+                    let counter = meter.f64_counter(metric.name).with_description(metric.help).init();
+                    sink.counters.insert(metric.name, counter);
+                }
+                MetricKind::Histogram => {
+                    let histogram = meter.f64_histogram(metric.name).with_description(metric.help).init();
+                    sink.histograms.insert(metric.name, histogram);
+                }
+                MetricKind::Gauge => {
+                    let values = GaugeValues::default();
+                    let observed = values.clone();
+                    let gauge = meter
+                        .f64_observable_gauge(metric.name)
+                        .with_description(metric.help)
+                        .with_callback(move |observer| {
+                            let values = observed.lock().unwrap_or_else(|e| e.into_inner());
+                            for (labels, value) in values.iter() {
+                                let attributes: Vec<KeyValue> = labels
+                                    .iter()
+                                    .map(|(key, value)| KeyValue::new(*key, value.clone()))
+                                    .collect();
+                                observer.observe(*value, &attributes);
+                            }
+                        })
+                        .init();
+                    sink.gauges.insert(metric.name, values);
+                    sink._observable_gauges.push(gauge);
+                }
+            }
+        }
+
+        Ok(sink)
+    }
+}
+
+impl MetricsSink for OtlpSink {
+    fn record(&self, metric: &MetricDef, labels: &[&str], value: f64) {
+        let labels: Vec<(&'static str, String)> = label_pairs(metric, labels)
+            .map(|(key, value)| (key, value.to_string()))
+            .collect();
+        let attributes = || -> Vec<KeyValue> {
+            labels
+                .iter()
+                .map(|(key, value)| KeyValue::new(*key, value.clone()))
+                .collect()
+        };
+
+        match metric.kind {
+            MetricKind::Counter => {
+                if let Some(counter) = self.counters.get(metric.name) {
+                    counter.add(value, &attributes());
+                }
+            }
+            MetricKind::Histogram => {
+                if let Some(histogram) = self.histograms.get(metric.name) {
+                    histogram.record(value, &attributes());
+                }
+            }
+            MetricKind::Gauge => {
+                if let Some(values) = self.gauges.get(metric.name) {
+                    values.lock().unwrap_or_else(|e| e.into_inner()).insert(labels, value);
+                }
+            }
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.provider
+            .force_flush()
+            .map_err(|e| RateLimitError::Service(format!("Failed to flush OTLP metrics: {}", e)))
+    }
+}
@@ -0,0 +1,181 @@
+use cadence::{prelude::*, MetricBuilder, StatsdClient, UdpMetricSink};
+use std::{net::UdpSocket, str::FromStr};
+
+use super::{
+    label_pairs, MetricDef, MetricKind, MetricsSink, CONFIG_LOAD_ERROR, CONFIG_LOAD_SUCCESS,
+    NEAR_LIMIT_REQUESTS, OVER_LIMIT_REQUESTS, SHADOW_MODE_REQUESTS, TOTAL_REQUESTS,
+    WITHIN_LIMIT_REQUESTS,
+};
+use crate::error::{RateLimitError, Result};
+
+/// How StatsD stat names are built
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatsdFormat {
+    /// Metric names as exposed to Prometheus, labels sent as DogStatsD tags
+    #[default]
+    Tagged,
+    /// Stat names of the Go ratelimit service, e.g.
+    /// `ratelimit.service.rate_limit.<domain>.<key>.over_limit`
+    Go,
+}
+
+impl FromStr for StatsdFormat {
+    type Err = RateLimitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "tagged" => Ok(StatsdFormat::Tagged),
+            "go" => Ok(StatsdFormat::Go),
+            other => Err(RateLimitError::Config(format!(
+                "unknown StatsD format '{}', expected tagged or go",
+                other
+            ))),
+        }
+    }
+}
+
+/// StatsD backend, sending each metric over UDP
+pub struct StatsdSink {
+    client: StatsdClient,
+    format: StatsdFormat,
+}
+
+impl StatsdSink {
+    /// Send metrics to the StatsD server at `address` (`host:port`)
+    pub fn new(address: &str, format: StatsdFormat) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_nonblocking(true)?;
+        let sink = UdpMetricSink::from(address, socket)
+            .map_err(|e| RateLimitError::Service(format!("Failed to create StatsD sink: {}", e)))?;
+
+        Ok(Self {
+            client: StatsdClient::from_sink("", sink),
+            format,
+        })
+    }
+
+    /// Stat name used by the Go service for a metric, if it has one
+    fn go_stat_name(metric: &MetricDef, labels: &[&str]) -> Option<String> {
+        let suffix = match metric.name {
+            name if name == TOTAL_REQUESTS.name => "total_hits",
+            name if name == OVER_LIMIT_REQUESTS.name => "over_limit",
+            name if name == NEAR_LIMIT_REQUESTS.name => "near_limit",
+            name if name == WITHIN_LIMIT_REQUESTS.name => "within_limit",
+            name if name == SHADOW_MODE_REQUESTS.name => "shadow_mode",
+            name if name == CONFIG_LOAD_SUCCESS.name => {
+                return Some("ratelimit.service.config_load_success".to_string());
+            }
+            name if name == CONFIG_LOAD_ERROR.name => {
+                return Some("ratelimit.service.config_load_error".to_string());
+            }
+            _ => return None,
+        };
+
+        // Descriptor paths use `:` between levels where Go uses `.`
+        let [domain, descriptor] = labels else {
+            return None;
+        };
+        let stat = format!(
+            "ratelimit.service.rate_limit.{}.{}.{}",
+            domain,
+            descriptor.replace(':', "."),
+            suffix
+        );
+        // `|` would end the StatsD name
+        Some(stat.replace('|', "_"))
+    }
+}
+
+impl MetricsSink for StatsdSink {
+    fn record(&self, metric: &MetricDef, labels: &[&str], value: f64) {
+        if self.format == StatsdFormat::Go {
+            if let Some(stat) = Self::go_stat_name(metric, labels) {
+                self.client.count(&stat, value as i64).ok();
+                return;
+            }
+        }
+
+        fn send<'m, T: cadence::Metric + From<String>>(
+            mut builder: MetricBuilder<'m, '_, T>,
+            metric: &MetricDef,
+            labels: &'m [&'m str],
+        ) {
+            for (key, value) in label_pairs(metric, labels) {
+                builder = builder.with_tag(key, value);
+            }
+            builder.send();
+        }
+
+        match metric.kind {
+            MetricKind::Counter => send(self.client.count_with_tags(metric.name, value as i64), metric, labels),
+            MetricKind::Gauge => send(self.client.gauge_with_tags(metric.name, value), metric, labels),
+            MetricKind::Histogram => send(self.client.histogram_with_tags(metric.name, value), metric, labels),
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.client
+            .flush()
+            .map_err(|e| RateLimitError::Service(format!("Failed to flush StatsD metrics: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{Metrics, REDIS_OPERATIONS};
+    use std::{sync::Arc, time::Duration};
+
+    fn receive(socket: &UdpSocket) -> String {
+        let mut buf = [0u8; 512];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }
+
+    #[test]
+    fn test_statsd_names_and_tags() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let sink = StatsdSink::new(&server.local_addr().unwrap().to_string(), StatsdFormat::Tagged).unwrap();
+
+        sink.record(&REDIS_OPERATIONS, &["pipeline", "success"], 1.0);
+        assert_eq!(
+            receive(&server),
+            "ratelimit_redis_operations:1|c|#operation:pipeline,result:success"
+        );
+
+        let metrics = Metrics::with_sink(Arc::new(prometheus::Registry::new()), Arc::new(sink));
+        metrics.set_redis_connections_active("primary", 1.0);
+        assert_eq!(receive(&server), "ratelimit_redis_connections_active:1|g|#instance:primary");
+
+        metrics.record_request_duration(0.25);
+        assert_eq!(receive(&server), "ratelimit_request_duration_seconds:0.25|h");
+    }
+
+    #[test]
+    fn test_statsd_go_stat_names() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let sink = StatsdSink::new(&server.local_addr().unwrap().to_string(), StatsdFormat::Go).unwrap();
+        let metrics = Metrics::with_sink(Arc::new(prometheus::Registry::new()), Arc::new(sink));
+
+        metrics.record_total_request("mongo_cps", "database_users");
+        assert_eq!(receive(&server), "ratelimit.service.rate_limit.mongo_cps.database_users.total_hits:1|c");
+
+        metrics.record_over_limit_request("edge", "path_/api:method_GET");
+        assert_eq!(receive(&server), "ratelimit.service.rate_limit.edge.path_/api.method_GET.over_limit:1|c");
+
+        metrics.record_near_limit_request("edge", "per_key");
+        assert_eq!(receive(&server), "ratelimit.service.rate_limit.edge.per_key.near_limit:1|c");
+
+        metrics.record_shadow_mode_request("edge", "per_key");
+        assert_eq!(receive(&server), "ratelimit.service.rate_limit.edge.per_key.shadow_mode:1|c");
+
+        metrics.record_config_load_success();
+        assert_eq!(receive(&server), "ratelimit.service.config_load_success:1|c");
+
+        // Metrics the Go service does not have keep their tagged names
+        metrics.record_local_cache_hit();
+        assert_eq!(receive(&server), "ratelimit_local_cache_hits:1|c");
+    }
+}
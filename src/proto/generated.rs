@@ -2,7 +2,22 @@
 // Include the generated protobuf code
 
 pub mod envoy {
+    pub mod config {
+        pub mod core {
+            pub mod v3 {
+                include!(concat!(env!("OUT_DIR"), "/envoy.config.core.v3.rs"));
+            }
+        }
+    }
+
     pub mod service {
+        pub mod discovery {
+            pub mod v3 {
+                // Include the generated ADS client and server
+                include!(concat!(env!("OUT_DIR"), "/envoy.service.discovery.v3.rs"));
+            }
+        }
+
         pub mod ratelimit {
             pub mod v3 {
                 // Include the generated protobuf code
@@ -12,7 +27,20 @@ pub mod envoy {
     }
 }
 
+pub mod google {
+    pub mod rpc {
+        include!(concat!(env!("OUT_DIR"), "/google.rpc.rs"));
+    }
+}
+
 pub mod ratelimit {
+    pub mod admin {
+        pub mod v3 {
+            // Include the generated config admin service
+            include!(concat!(env!("OUT_DIR"), "/ratelimit.admin.v3.rs"));
+        }
+    }
+
     pub mod config {
         pub mod v3 {
             // Include the generated config protobuf code
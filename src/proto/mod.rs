@@ -1,6 +1,22 @@
 // Generated protobuf types and gRPC service definitions
 pub mod generated;
 
+/// Encoded `FileDescriptorSet` for the service protos, used by gRPC reflection
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ratelimit_descriptor.bin"));
+
+/// gRPC reflection for the rate limit and config admin services and `grpc.health.v1.Health`
+pub fn reflection_service() -> Result<
+    tonic_reflection::pb::server_reflection_server::ServerReflectionServer<
+        impl tonic_reflection::pb::server_reflection_server::ServerReflection,
+    >,
+    tonic_reflection::server::Error,
+> {
+    tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()
+}
+
 // Re-export the main types for easy access
 pub use generated::envoy::service::ratelimit::v3::{
     RateLimitRequest, RateLimitResponse, RateLimitDescriptor, RateLimitDescriptorEntry,
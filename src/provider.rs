@@ -0,0 +1,513 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tracing::{info, warn};
+
+use crate::{
+    config::{load_config_from_file, CompiledRateLimitConfig, ConfigFormat, RateLimitConfig},
+    error::{RateLimitError, Result},
+    metrics::Metrics,
+    service::RateLimitService,
+    shutdown::Shutdown,
+};
+
+/// Default interval between checks of a watched config directory
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Every domain config a provider currently provides
+pub type ConfigSnapshot = Vec<CompiledRateLimitConfig>;
+
+/// Snapshots sent by a provider. An error reports a failed reload; the previous
+/// snapshot stays applied.
+pub type ConfigSnapshots = Pin<Box<dyn Stream<Item = Result<ConfigSnapshot>> + Send>>;
+
+/// A source of rate limit configs, such as files, a management server or a database.
+/// Each snapshot replaces the previous one from the same provider: domains missing
+/// from a snapshot are removed.
+pub trait ConfigProvider: Send {
+    /// Describes the provider in logs and admin API errors
+    fn name(&self) -> String;
+
+    /// Whether startup waits for the first snapshot and fails when it cannot be
+    /// loaded. Providers whose source may be unreachable for a while return false.
+    fn required(&self) -> bool {
+        true
+    }
+
+    /// Directory the provider loads config files from. The admin API may change the
+    /// provider's domains when it persists changes to the same directory.
+    fn config_dir(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Start providing snapshots. The stream ends when the source has nothing more
+    /// to send, and dropping it stops the provider.
+    fn snapshots(self: Box<Self>) -> ConfigSnapshots;
+}
+
+/// Compile configs into a snapshot, rejecting it as a whole if any config is
+/// invalid or two share a domain
+pub fn compile_snapshot(configs: Vec<RateLimitConfig>) -> Result<ConfigSnapshot> {
+    let mut domains = HashSet::new();
+    let mut snapshot = Vec::with_capacity(configs.len());
+    for config in configs {
+        if !domains.insert(config.domain.clone()) {
+            return Err(RateLimitError::Config(format!(
+                "duplicate config for domain '{}'",
+                config.domain
+            )));
+        }
+        snapshot.push(CompiledRateLimitConfig::compile(config)?);
+    }
+    Ok(snapshot)
+}
+
+/// Configs given up front, provided once
+pub struct StaticConfigProvider {
+    configs: Vec<RateLimitConfig>,
+}
+
+impl StaticConfigProvider {
+    pub fn new(configs: Vec<RateLimitConfig>) -> Self {
+        Self { configs }
+    }
+}
+
+impl ConfigProvider for StaticConfigProvider {
+    fn name(&self) -> String {
+        "static configs".to_string()
+    }
+
+    fn snapshots(self: Box<Self>) -> ConfigSnapshots {
+        Box::pin(tokio_stream::once(compile_snapshot(self.configs)))
+    }
+}
+
+/// A single config file, loaded once
+pub struct FileConfigProvider {
+    path: PathBuf,
+}
+
+impl FileConfigProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ConfigProvider for FileConfigProvider {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn snapshots(self: Box<Self>) -> ConfigSnapshots {
+        let snapshot = load_config_from_file(&self.path)
+            .and_then(|config| compile_snapshot(vec![config]));
+        Box::pin(tokio_stream::once(snapshot))
+    }
+}
+
+// Name, size and modification time of each config file, compared between polls
+type DirectoryFingerprint = Vec<(PathBuf, u64, Option<SystemTime>)>;
+
+/// One config file per domain in a directory, e.g. as written by the admin API.
+/// The directory is polled and reloaded as a whole when any file changes.
+pub struct DirectoryConfigProvider {
+    dir: PathBuf,
+    poll_interval: Duration,
+}
+
+impl DirectoryConfigProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Check the directory for changes every `interval`
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Load every config file into a snapshot
+    pub fn load(&self) -> Result<ConfigSnapshot> {
+        let configs = config_files(&self.dir)?
+            .iter()
+            .map(load_config_from_file)
+            .collect::<Result<_>>()?;
+        compile_snapshot(configs)
+    }
+
+    fn fingerprint(&self) -> Option<DirectoryFingerprint> {
+        let files = config_files(&self.dir).ok()?;
+        Some(
+            files
+                .into_iter()
+                .map(|path| {
+                    let metadata = std::fs::metadata(&path).ok();
+                    let len = metadata.as_ref().map_or(0, |metadata| metadata.len());
+                    let modified = metadata.and_then(|metadata| metadata.modified().ok());
+                    (path, len, modified)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ConfigProvider for DirectoryConfigProvider {
+    fn name(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    fn config_dir(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+
+    fn snapshots(self: Box<Self>) -> ConfigSnapshots {
+        let (snapshots, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            let mut last: Option<Option<DirectoryFingerprint>> = None;
+            loop {
+                let fingerprint = self.fingerprint();
+                if last.as_ref() != Some(&fingerprint) {
+                    if last.is_some() {
+                        info!("Config directory {} changed, reloading", self.dir.display());
+                    }
+                    last = Some(fingerprint);
+                    if snapshots.send(self.load()).await.is_err() {
+                        return;
+                    }
+                }
+
+                tokio::select! {
+                    _ = tokio::time::sleep(self.poll_interval) => {}
+                    _ = snapshots.closed() => return,
+                }
+            }
+        });
+        Box::pin(ReceiverStream::new(receiver))
+    }
+}
+
+/// The YAML, JSON and TOML files in `dir`, in name order
+pub fn config_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    paths.retain(|path| ConfigFormat::from_path(path).is_some());
+    paths.sort();
+    Ok(paths)
+}
+
+/// Provider supplying a domain
+#[derive(Debug, Clone)]
+pub struct DomainOwner {
+    pub name: String,
+    pub config_dir: Option<PathBuf>,
+}
+
+/// The domains each provider supplies. When several providers send the same domain,
+/// the one registered first supplies it, and the next one takes over when it stops.
+/// Domains no provider sends, such as those created through the admin API, are
+/// replaced by any provider that sends them.
+#[derive(Clone)]
+pub struct ConfigSources {
+    service: Arc<RateLimitService>,
+    metrics: Arc<Metrics>,
+    providers: Arc<std::sync::Mutex<Vec<ProviderConfigs>>>,
+    // Serializes applying snapshots so the service matches the registry
+    applying: Arc<Mutex<()>>,
+}
+
+// Domains of a provider's last applied snapshot
+struct ProviderConfigs {
+    owner: DomainOwner,
+    configs: HashMap<String, RateLimitConfig>,
+}
+
+impl ConfigSources {
+    pub fn new(service: Arc<RateLimitService>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            service,
+            metrics,
+            providers: Arc::default(),
+            applying: Arc::default(),
+        }
+    }
+
+    /// Register a provider after the existing ones, returning the applier for its snapshots
+    pub fn register(&self, name: impl Into<String>, config_dir: Option<PathBuf>) -> ConfigApplier {
+        let mut providers = self.providers.lock().unwrap();
+        let name = name.into();
+        providers.push(ProviderConfigs {
+            owner: DomainOwner {
+                name: name.clone(),
+                config_dir,
+            },
+            configs: HashMap::new(),
+        });
+        ConfigApplier {
+            name,
+            id: providers.len() - 1,
+            sources: self.clone(),
+        }
+    }
+
+    /// Apply the snapshots of `providers`, which take precedence in the order given.
+    /// Waits for the first snapshot of each required provider, failing when it cannot
+    /// be loaded, then applies later snapshots in the background until shutdown.
+    pub async fn start(&self, providers: Vec<Box<dyn ConfigProvider>>, shutdown: &Shutdown) -> Result<()> {
+        for provider in providers {
+            let required = provider.required();
+            let mut applier = self.register(provider.name(), provider.config_dir().map(Path::to_path_buf));
+            let mut snapshots = provider.snapshots();
+            if required {
+                info!("Loading configs from {}", applier.name);
+                if let Some(snapshot) = snapshots.next().await {
+                    applier.apply(snapshot?).await?;
+                }
+            }
+            tokio::spawn(applier.run(snapshots, shutdown.clone()));
+        }
+        Ok(())
+    }
+
+    /// The provider supplying `domain`, if any
+    pub fn owner(&self, domain: &str) -> Option<DomainOwner> {
+        let providers = self.providers.lock().unwrap();
+        providers
+            .iter()
+            .find(|provider| provider.configs.contains_key(domain))
+            .map(|provider| provider.owner.clone())
+    }
+
+    async fn apply(&self, id: usize, snapshot: ConfigSnapshot) -> Result<(usize, usize)> {
+        let _applying = self.applying.lock().await;
+        let configs: HashMap<String, RateLimitConfig> = snapshot
+            .iter()
+            .map(|config| (config.domain().to_string(), config.source().clone()))
+            .collect();
+
+        let (applied, removed) = {
+            let providers = self.providers.lock().unwrap();
+            // The first provider sending the domain, counting this snapshot
+            let owner = |domain: &str| {
+                providers.iter().enumerate().position(|(i, provider)| {
+                    if i == id {
+                        configs.contains_key(domain)
+                    } else {
+                        provider.configs.contains_key(domain)
+                    }
+                })
+            };
+
+            let mut applied = Vec::new();
+            for config in snapshot {
+                match owner(config.domain()) {
+                    Some(owner) if owner < id => warn!(
+                        "Ignoring domain {} from {}: provided by {}",
+                        config.domain(),
+                        providers[id].owner.name,
+                        providers[owner].owner.name
+                    ),
+                    _ => applied.push(config),
+                }
+            }
+            let mut removed = Vec::new();
+            for domain in providers[id].configs.keys().filter(|domain| !configs.contains_key(*domain)) {
+                match owner(domain) {
+                    None => removed.push(domain.clone()),
+                    // Hand the domain over to the next provider sending it
+                    Some(owner) if owner > id => {
+                        info!("Domain {} is now provided by {}", domain, providers[owner].owner.name);
+                        applied.push(CompiledRateLimitConfig::compile(providers[owner].configs[domain].clone())?);
+                    }
+                    Some(_) => {}
+                }
+            }
+            (applied, removed)
+        };
+
+        let counts = (applied.len(), removed.len());
+        self.service.replace_configs(applied, &removed).await?;
+        self.providers.lock().unwrap()[id].configs = configs;
+        Ok(counts)
+    }
+}
+
+/// Applies the snapshots of one provider to the service
+pub struct ConfigApplier {
+    name: String,
+    id: usize,
+    sources: ConfigSources,
+}
+
+impl ConfigApplier {
+    /// An applier for the provider described by `name` in logs, as the only provider
+    pub fn new(name: impl Into<String>, service: Arc<RateLimitService>, metrics: Arc<Metrics>) -> Self {
+        ConfigSources::new(service, metrics).register(name, None)
+    }
+
+    /// Apply a snapshot, removing the domains of the previous one that it lacks.
+    /// Domains this provider never sent are left alone, as are domains an earlier
+    /// registered provider supplies.
+    pub async fn apply(&mut self, snapshot: ConfigSnapshot) -> Result<()> {
+        let (applied, removed) = self.sources.apply(self.id, snapshot).await?;
+        info!(
+            "Applied configs from {}: {} domains, {} removed",
+            self.name, applied, removed
+        );
+        Ok(())
+    }
+
+    /// Apply snapshots until the stream ends or shutdown begins. Failed reloads are
+    /// logged and counted, leaving the last good snapshot in place.
+    pub async fn run(mut self, mut snapshots: ConfigSnapshots, shutdown: Shutdown) {
+        loop {
+            let snapshot = tokio::select! {
+                snapshot = snapshots.next() => snapshot,
+                _ = shutdown.draining() => return,
+            };
+            let result = match snapshot {
+                Some(Ok(snapshot)) => self.apply(snapshot).await,
+                Some(Err(e)) => Err(e),
+                None => return,
+            };
+            if let Err(e) = result {
+                warn!("Failed to load configs from {}: {}", self.name, e);
+                self.sources.metrics.record_config_load_error();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::RedisRateLimitCache,
+        limiter::RateLimiter,
+        redis::{RedisClientPool, RedisConfig},
+    };
+
+    fn config(domain: &str) -> RateLimitConfig {
+        crate::config::load_config_from_yaml(&format!(
+            "domain: {}\ndescriptors:\n  - key: user\n    rate_limit: {{ requests_per_unit: 1, unit: second }}\n",
+            domain
+        ))
+        .unwrap()
+    }
+
+    async fn domains(service: &RateLimitService) -> Vec<String> {
+        let limiter = service.limiter().await;
+        limiter.configs().iter().map(|config| config.domain().to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_applier_removes_only_its_own_domains() {
+        let redis_pool = RedisClientPool::new_single(RedisConfig::default()).await.unwrap();
+        let cache = RedisRateLimitCache::new(redis_pool, 1000, 0.8, "test".to_string());
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let service = Arc::new(RateLimitService::new(RateLimiter::new(Box::new(cache)), metrics.clone()));
+        service
+            .add_config(CompiledRateLimitConfig::compile(config("other")).unwrap())
+            .await
+            .unwrap();
+
+        let mut applier = ConfigApplier::new("test", service.clone(), metrics);
+        applier.apply(compile_snapshot(vec![config("a"), config("b")]).unwrap()).await.unwrap();
+        assert_eq!(domains(&service).await, ["a", "b", "other"]);
+        applier.apply(compile_snapshot(vec![config("b")]).unwrap()).await.unwrap();
+        assert_eq!(domains(&service).await, ["b", "other"]);
+
+        let err = compile_snapshot(vec![config("a"), config("a")]).unwrap_err();
+        assert!(err.to_string().contains("duplicate config for domain 'a'"));
+
+        let mut statics = Box::new(StaticConfigProvider::new(vec![config("c")])).snapshots();
+        assert_eq!(statics.next().await.unwrap().unwrap()[0].domain(), "c");
+        assert!(statics.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sources_prefer_earlier_providers() {
+        let redis_pool = RedisClientPool::new_single(RedisConfig::default()).await.unwrap();
+        let cache = RedisRateLimitCache::new(redis_pool, 1000, 0.8, "test".to_string());
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let service = Arc::new(RateLimitService::new(RateLimiter::new(Box::new(cache)), metrics.clone()));
+        let sources = ConfigSources::new(service.clone(), metrics);
+        let mut files = sources.register("files", Some(PathBuf::from("/configs")));
+        let mut xds = sources.register("xds", None);
+
+        let limit = |domain: &str, requests_per_unit: u32| {
+            let mut config = config(domain);
+            config.descriptors[0].rate_limit.as_mut().unwrap().requests_per_unit = requests_per_unit;
+            config
+        };
+        let requests_per_unit = |domain: &'static str| {
+            let service = service.clone();
+            async move {
+                let limiter = service.limiter().await;
+                limiter.get_config(domain).unwrap().find_limit(&[("user", "x")]).unwrap().requests_per_unit
+            }
+        };
+
+        // The earlier provider keeps the shared domain whichever snapshot came last
+        xds.apply(compile_snapshot(vec![limit("shared", 2), config("b")]).unwrap()).await.unwrap();
+        assert_eq!(requests_per_unit("shared").await, 2);
+        files.apply(compile_snapshot(vec![limit("shared", 1)]).unwrap()).await.unwrap();
+        xds.apply(compile_snapshot(vec![limit("shared", 3), config("b")]).unwrap()).await.unwrap();
+        assert_eq!(requests_per_unit("shared").await, 1);
+        assert_eq!(sources.owner("shared").unwrap().name, "files");
+        assert_eq!(sources.owner("b").unwrap().name, "xds");
+        assert!(sources.owner("other").is_none());
+
+        // and hands it over when it stops providing it
+        files.apply(Vec::new()).await.unwrap();
+        assert_eq!(requests_per_unit("shared").await, 3);
+        assert_eq!(sources.owner("shared").unwrap().name, "xds");
+        xds.apply(Vec::new()).await.unwrap();
+        assert!(domains(&service).await.is_empty());
+    }
+
+    async fn next_snapshot(snapshots: &mut ConfigSnapshots) -> Result<ConfigSnapshot> {
+        tokio::time::timeout(Duration::from_secs(5), snapshots.next())
+            .await
+            .expect("timed out waiting for a snapshot")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_directory_provider_reloads_on_change() {
+        let dir = std::env::temp_dir().join(format!("ratelimit_provider_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.yaml"), serde_yaml::to_string(&config("a")).unwrap()).unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let provider = DirectoryConfigProvider::new(&dir).with_poll_interval(Duration::from_millis(20));
+        let mut snapshots = Box::new(provider).snapshots();
+
+        let snapshot = next_snapshot(&mut snapshots).await.unwrap();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].domain(), "a");
+
+        std::fs::write(dir.join("b.yml"), serde_yaml::to_string(&config("b")).unwrap()).unwrap();
+        let domains: Vec<_> = next_snapshot(&mut snapshots).await.unwrap().iter().map(|config| config.domain().to_string()).collect();
+        assert_eq!(domains, ["a", "b"]);
+
+        // A broken file fails the reload until it is fixed
+        std::fs::write(dir.join("b.yml"), "domain: [").unwrap();
+        assert!(next_snapshot(&mut snapshots).await.is_err());
+        std::fs::remove_file(dir.join("b.yml")).unwrap();
+        assert_eq!(next_snapshot(&mut snapshots).await.unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
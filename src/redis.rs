@@ -1,6 +1,13 @@
 use redis::{aio::ConnectionManager, AsyncCommands, RedisResult};
-use std::time::Duration;
-use crate::error::{Result, RateLimitError};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use crate::{
+    error::{Result, RateLimitError},
+    health::DependencyHealth,
+    metrics::Metrics,
+};
 
 /// Redis client configuration
 #[derive(Debug, Clone)]
@@ -29,6 +36,8 @@ impl Default for RedisConfig {
 pub struct RedisClient {
     connection: ConnectionManager,
     config: RedisConfig,
+    metrics: Option<Arc<Metrics>>,
+    instance: &'static str,
 }
 
 impl RedisClient {
@@ -98,10 +107,50 @@ impl RedisClient {
         }
 
         info!("Redis client initialized successfully");
-        Ok(Self { connection, config })
+        Ok(Self {
+            connection,
+            config,
+            metrics: None,
+            instance: "primary",
+        })
+    }
+
+    /// Record operation metrics under the given instance label
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>, instance: &'static str) -> Self {
+        // The connection was verified with PING on creation
+        metrics.set_redis_connections_active(instance, 1.0);
+        self.metrics = Some(metrics);
+        self.instance = instance;
+        self
+    }
+
+    /// Record the outcome and duration of an operation
+    fn record<T>(&self, operation: &str, started: Instant, result: &RedisResult<T>) {
+        let Some(metrics) = &self.metrics else {
+            return;
+        };
+
+        metrics.record_redis_operation_duration(operation, started.elapsed().as_secs_f64());
+        match result {
+            Ok(_) => {
+                metrics.record_redis_operation(operation, "success");
+                metrics.set_redis_connections_active(self.instance, 1.0);
+            }
+            Err(e) => {
+                metrics.record_redis_operation(operation, "error");
+                if e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() {
+                    metrics.set_redis_connections_active(self.instance, 0.0);
+                }
+            }
+        }
     }
 
     /// Increment a key by the given amount and set expiration
+    #[tracing::instrument(
+        name = "redis.pipeline",
+        skip_all,
+        fields(db.system = "redis", redis.instance = self.instance, redis.keys = 1)
+    )]
     pub async fn increment_and_expire(
         &self,
         key: &str,
@@ -111,13 +160,15 @@ impl RedisClient {
         let mut conn = self.connection.clone();
         
         if self.config.enable_pipelining {
-            let pipe = redis::pipe()
+            let started = Instant::now();
+            let result = redis::pipe()
                 .atomic()
                 .incr(key, increment)
                 .expire(key, expire_seconds as i64)
                 .query_async(&mut conn)
-                .await
-                .map_err(RateLimitError::Redis)?;
+                .await;
+            self.record("pipeline", started, &result);
+            let pipe = result.map_err(RateLimitError::Redis)?;
             
             match pipe {
                 redis::Value::Bulk(values) if !values.is_empty() => {
@@ -137,8 +188,15 @@ impl RedisClient {
             }
         } else {
             // Execute commands sequentially if pipelining is disabled
-            let count: u64 = conn.incr(key, increment).await.map_err(RateLimitError::Redis)?;
-            let _: bool = conn.expire(key, expire_seconds as i64).await.map_err(RateLimitError::Redis)?;
+            let started = Instant::now();
+            let result = conn.incr(key, increment).await;
+            self.record("incr", started, &result);
+            let count: u64 = result.map_err(RateLimitError::Redis)?;
+
+            let started = Instant::now();
+            let result: RedisResult<bool> = conn.expire(key, expire_seconds as i64).await;
+            self.record("expire", started, &result);
+            result.map_err(RateLimitError::Redis)?;
             Ok(count)
         }
     }
@@ -146,7 +204,9 @@ impl RedisClient {
     /// Get the current value of a key
     pub async fn get(&self, key: &str) -> Result<Option<u64>> {
         let mut conn = self.connection.clone();
+        let started = Instant::now();
         let result: RedisResult<u64> = conn.get(key).await;
+        self.record("get", started, &result);
         
         match result {
             Ok(value) => Ok(Some(value)),
@@ -161,7 +221,46 @@ impl RedisClient {
         }
     }
 
+    /// Seconds until a key expires; `None` if it does not exist or never expires
+    pub async fn ttl(&self, key: &str) -> Result<Option<u64>> {
+        let mut conn = self.connection.clone();
+        let started = Instant::now();
+        let result: RedisResult<i64> = conn.ttl(key).await;
+        self.record("ttl", started, &result);
+        let ttl = result.map_err(RateLimitError::Redis)?;
+        Ok(u64::try_from(ttl).ok())
+    }
+
+    /// Set a key to a value that expires after the given number of seconds
+    pub async fn set_with_expire(&self, key: &str, value: u64, expire_seconds: u64) -> Result<()> {
+        let mut conn = self.connection.clone();
+        let started = Instant::now();
+        let result: RedisResult<()> = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("EX")
+            .arg(expire_seconds)
+            .query_async(&mut conn)
+            .await;
+        self.record("set", started, &result);
+        result.map_err(RateLimitError::Redis)
+    }
+
+    /// Delete a key
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        let mut conn = self.connection.clone();
+        let started = Instant::now();
+        let result: RedisResult<()> = conn.del(key).await;
+        self.record("del", started, &result);
+        result.map_err(RateLimitError::Redis)
+    }
+
     /// Execute multiple increment and expire operations in a pipeline
+    #[tracing::instrument(
+        name = "redis.pipeline",
+        skip_all,
+        fields(db.system = "redis", redis.instance = self.instance, redis.keys = operations.len())
+    )]
     pub async fn pipeline_increment_and_expire(
         &self,
         operations: Vec<(String, u64, u64)>,
@@ -179,10 +278,10 @@ impl RedisClient {
             pipe.incr(key, *increment).expire(key, *expire_seconds as i64);
         }
 
-        let results: Vec<redis::Value> = pipe
-            .query_async(&mut conn)
-            .await
-            .map_err(RateLimitError::Redis)?;
+        let started = Instant::now();
+        let result = pipe.query_async(&mut conn).await;
+        self.record("pipeline", started, &result);
+        let results: Vec<redis::Value> = result.map_err(RateLimitError::Redis)?;
 
         // Extract increment results (every 2nd value is the INCR result)
         let mut counts = Vec::new();
@@ -203,8 +302,20 @@ impl RedisClient {
     /// Check if the connection is healthy
     pub async fn health_check(&self) -> Result<()> {
         let mut conn = self.connection.clone();
-        redis::cmd("PING").query_async::<_, ()>(&mut conn).await.map_err(RateLimitError::Redis)?;
-        Ok(())
+        let started = Instant::now();
+        let result = redis::cmd("PING").query_async::<_, ()>(&mut conn).await;
+        self.record("ping", started, &result);
+        result.map_err(RateLimitError::Redis)
+    }
+
+    /// PING the server, reporting latency as `redis_<instance>`
+    pub async fn dependency_health(&self) -> DependencyHealth {
+        DependencyHealth::check(
+            format!("redis_{}", self.instance),
+            self.config.command_timeout.unwrap_or(Duration::from_secs(5)),
+            self.health_check(),
+        )
+        .await
     }
 }
 
@@ -263,8 +374,9 @@ impl RedisClientPool {
         
         info!("Creating per-second Redis client...");
         let per_second_client = match RedisClient::new(per_second_config).await {
-            Ok(client) => {
+            Ok(mut client) => {
                 info!("Per-second Redis client created successfully");
+                client.instance = "per_second";
                 Some(client)
             }
             Err(e) => {
@@ -280,12 +392,20 @@ impl RedisClientPool {
         })
     }
 
+    /// Record operation metrics for all clients in the pool
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.primary_client = self.primary_client.with_metrics(metrics.clone(), "primary");
+        self.per_second_client = self
+            .per_second_client
+            .map(|client| client.with_metrics(metrics, "per_second"));
+        self
+    }
+
     /// Get the appropriate client for the given operation
     pub fn get_client(&self, is_per_second: bool) -> &RedisClient {
-        if is_per_second && self.per_second_client.is_some() {
-            self.per_second_client.as_ref().unwrap()
-        } else {
-            &self.primary_client
+        match &self.per_second_client {
+            Some(client) if is_per_second => client,
+            _ => &self.primary_client,
         }
     }
 
@@ -297,6 +417,15 @@ impl RedisClientPool {
         }
         Ok(())
     }
+
+    /// Check each client separately
+    pub async fn dependency_health(&self) -> Vec<DependencyHealth> {
+        let mut dependencies = vec![self.primary_client.dependency_health().await];
+        if let Some(per_second_client) = &self.per_second_client {
+            dependencies.push(per_second_client.dependency_health().await);
+        }
+        dependencies
+    }
 }
 
 #[cfg(test)]
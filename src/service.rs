@@ -1,9 +1,11 @@
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockReadGuard};
 
 use crate::{
     cache::{RateLimitDescriptor, RateLimitRequest, ResponseCode},
     config::CompiledRateLimitConfig,
+    health::DependencyHealth,
     limiter::{RateLimiter, RateLimitResponse},
     metrics::Metrics,
 };
@@ -11,31 +13,40 @@ use crate::{
 // Simplified protobuf-like structures for this implementation
 // In a production system, these would be generated from .proto files
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrpcRateLimitRequest {
     pub domain: String,
     pub descriptors: Vec<GrpcRateLimitDescriptor>,
+    /// Treated as 1 when zero or omitted
+    #[serde(default)]
     pub hits_addend: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrpcRateLimitDescriptor {
     pub entries: Vec<GrpcRateLimitDescriptorEntry>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrpcRateLimitDescriptorEntry {
     pub key: String,
     pub value: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrpcRateLimitResponse {
     pub overall_code: i32,
     pub statuses: Vec<GrpcDescriptorStatus>,
 }
 
-#[derive(Debug, Clone)]
+impl GrpcRateLimitResponse {
+    /// Whether the request as a whole is over limit
+    pub fn is_over_limit(&self) -> bool {
+        self.overall_code == RateLimitService::convert_response_code(ResponseCode::OverLimit)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrpcDescriptorStatus {
     pub code: i32,
     pub current_limit: Option<GrpcRateLimit>,
@@ -43,7 +54,7 @@ pub struct GrpcDescriptorStatus {
     pub duration_until_reset_secs: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrpcRateLimit {
     pub requests_per_unit: u32,
     pub unit: i32,
@@ -81,12 +92,41 @@ impl RateLimitService {
         Ok(())
     }
 
+    /// Add `configs` and remove the `removed` domains in one step, so requests see
+    /// either the old set of configs or the new one
+    pub async fn replace_configs(
+        &self,
+        configs: Vec<CompiledRateLimitConfig>,
+        removed: &[String],
+    ) -> crate::error::Result<()> {
+        let mut limiter = self.limiter.write().await;
+        for domain in removed {
+            limiter.remove_config(domain);
+        }
+        for config in configs {
+            limiter.add_config(config);
+        }
+        self.metrics.record_config_load_success();
+        Ok(())
+    }
+
+    /// Read access to the limiter, for inspecting the loaded configuration
+    pub async fn limiter(&self) -> RwLockReadGuard<'_, RateLimiter> {
+        self.limiter.read().await
+    }
+
     /// Health check for the service
     pub async fn health_check(&self) -> crate::error::Result<()> {
         let limiter = self.limiter.read().await;
         limiter.health_check().await
     }
 
+    /// Per-dependency health used by the readiness endpoint
+    pub async fn dependency_health(&self) -> Vec<DependencyHealth> {
+        let limiter = self.limiter.read().await;
+        limiter.dependency_health().await
+    }
+
     /// Convert internal response code to gRPC response code
     fn convert_response_code(code: ResponseCode) -> i32 {
         match code {
@@ -110,7 +150,9 @@ impl RateLimitService {
                         crate::utils::Unit::Second => 1,
                         crate::utils::Unit::Minute => 2,
                         crate::utils::Unit::Hour => 3,
-                        crate::utils::Unit::Day => 4,
+                        crate::utils::Unit::Day
+                        | crate::utils::Unit::Week
+                        | crate::utils::Unit::Month => 4,
                     },
                 }),
                 limit_remaining: status.limit_remaining,
@@ -153,16 +195,6 @@ impl RateLimitService {
             hits_addend: req.hits_addend,
         };
 
-        // Record metrics
-        for descriptor in &internal_request.descriptors {
-            let descriptor_key = if descriptor.entries.is_empty() {
-                "unknown".to_string()
-            } else {
-                descriptor.entries[0].0.clone()
-            };
-            self.metrics.record_total_request(&req.domain, &descriptor_key);
-        }
-
         // Process the request
         let result = {
             let limiter = self.limiter.read().await;
@@ -173,22 +205,9 @@ impl RateLimitService {
 
         match result {
             Ok(response) => {
-                // Record additional metrics based on response
-                for (i, status) in response.statuses.iter().enumerate() {
-                    let descriptor_key = if internal_request.descriptors[i].entries.is_empty() {
-                        "unknown".to_string()
-                    } else {
-                        internal_request.descriptors[i].entries[0].0.clone()
-                    };
-
-                    match status.code {
-                        ResponseCode::Ok => {
-                            self.metrics.record_within_limit_request(&req.domain, &descriptor_key);
-                        }
-                        ResponseCode::OverLimit => {
-                            self.metrics.record_over_limit_request(&req.domain, &descriptor_key);
-                        }
-                    }
+                // Record metrics labelled by the matched rule, counting hits as the Go service does
+                for (status, descriptor_key) in response.statuses.iter().zip(&response.metric_keys) {
+                    self.metrics.record_hits(&req.domain, descriptor_key, &status.hits);
                 }
 
                 let grpc_response = Self::convert_response(response);
@@ -230,17 +249,31 @@ mod tests {
     async fn test_config_management() {
         let service = create_test_service().await;
 
+        // Not ready until a configuration is loaded
+        let dependencies = service.dependency_health().await;
+        assert_eq!(dependencies[0].name, "config");
+        assert!(!dependencies[0].healthy);
+
         let config = RateLimitConfig {
             domain: "test".to_string(),
             descriptors: vec![ConfigDescriptor {
                 key: "key1".to_string(),
                 value: Some("value1".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 100,
                     unit: RateLimitUnit::Second,
+                    unit_multiplier: None,
+                    calendar: None,
+                    timezone: None,
                     unlimited: None,
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: None,
             }],
@@ -248,6 +281,11 @@ mod tests {
 
         let compiled_config = crate::config::CompiledRateLimitConfig::compile(config).unwrap();
         service.add_config(compiled_config).await.unwrap();
+
+        let dependencies = service.dependency_health().await;
+        let names: Vec<_> = dependencies.iter().map(|dependency| dependency.name.as_str()).collect();
+        assert_eq!(names, ["config", "redis_primary"]);
+        assert!(dependencies.iter().all(|dependency| dependency.healthy));
     }
 
     #[tokio::test]
@@ -275,4 +313,37 @@ mod tests {
             _ => panic!("Expected service error"),
         }
     }
+
+    #[tokio::test]
+    async fn test_should_rate_limit_json() {
+        let service = create_test_service().await;
+        let yaml = r#"
+domain: json_test
+descriptors:
+  - key: client
+    rate_limit:
+      requests_per_unit: 1
+      unit: hour
+"#;
+        let config = crate::config::load_config_from_yaml(yaml).unwrap();
+        service.add_config(CompiledRateLimitConfig::compile(config).unwrap()).await.unwrap();
+
+        // Same shape as the gRPC request; hits_addend may be omitted
+        let body = format!(
+            r#"{{"domain":"json_test","descriptors":[{{"entries":[{{"key":"client","value":"cron-{}"}}]}}]}}"#,
+            std::process::id()
+        );
+        let request: GrpcRateLimitRequest = serde_json::from_str(&body).unwrap();
+        assert_eq!(request.hits_addend, 0);
+
+        let first = service.should_rate_limit_direct(request.clone()).await.unwrap();
+        assert!(!first.is_over_limit());
+        let second = service.should_rate_limit_direct(request).await.unwrap();
+        assert!(second.is_over_limit());
+
+        let json = serde_json::to_value(&second).unwrap();
+        assert_eq!(json["overall_code"], 2);
+        assert_eq!(json["statuses"][0]["current_limit"]["requests_per_unit"], 1);
+        assert_eq!(json["statuses"][0]["current_limit"]["unit"], 3);
+    }
 }
\ No newline at end of file
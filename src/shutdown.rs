@@ -0,0 +1,139 @@
+use std::{sync::Arc, time::Duration};
+use tokio::sync::watch;
+use tracing::info;
+
+/// Default time between failing readiness and closing the listeners
+pub const DEFAULT_DRAIN_PERIOD: Duration = Duration::from_secs(5);
+
+/// Default time in-flight requests get to finish once the listeners are closed
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Coordinates a graceful shutdown: readiness is failed first so load balancers
+/// stop routing, then the servers are told to stop accepting and finish in-flight requests
+#[derive(Clone)]
+pub struct Shutdown {
+    ready: Arc<watch::Sender<bool>>,
+    stop: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    /// Create a handle in the ready state
+    pub fn new() -> Self {
+        let (ready, _) = watch::channel(true);
+        let (stop, _) = watch::channel(false);
+        Self {
+            ready: Arc::new(ready),
+            stop: Arc::new(stop),
+        }
+    }
+
+    /// Whether the service should receive new traffic
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Fail readiness checks while still serving requests
+    pub fn begin(&self) {
+        self.ready.send_replace(false);
+    }
+
+    /// Resolves once readiness has started failing
+    pub async fn draining(&self) {
+        let mut ready = self.ready.subscribe();
+        let _ = ready.wait_for(|ready| !*ready).await;
+    }
+
+    /// Tell the servers to stop accepting connections
+    pub fn stop(&self) {
+        self.begin();
+        self.stop.send_replace(true);
+    }
+
+    /// Resolves once `stop` has been called
+    pub async fn stopped(&self) {
+        let mut stop = self.stop.subscribe();
+        // The sender lives in `self`, so this only returns once stop is signalled
+        let _ = stop.wait_for(|stopped| *stopped).await;
+    }
+
+    /// Fail readiness, wait for the drain period, then stop the servers
+    pub async fn drain(&self, drain_period: Duration) {
+        self.begin();
+        info!("Readiness set to failing, draining for {}s", drain_period.as_secs());
+        tokio::time::sleep(drain_period).await;
+        self.stop();
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wait for SIGTERM or Ctrl+C
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => info!("Received SIGTERM, shutting down"),
+                    _ = tokio::signal::ctrl_c() => info!("Received Ctrl+C, shutting down"),
+                }
+                return;
+            }
+            Err(e) => tracing::warn!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+    info!("Received Ctrl+C, shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_sequence() {
+        let shutdown = Shutdown::new();
+        assert!(shutdown.is_ready());
+
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.stopped().await }
+        });
+
+        shutdown.begin();
+        assert!(!shutdown.is_ready());
+        tokio::time::timeout(Duration::from_secs(1), shutdown.draining()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        shutdown.stop();
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+
+        // Waiting after stop returns immediately
+        tokio::time::timeout(Duration::from_secs(1), shutdown.stopped()).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_waits_before_stopping() {
+        let shutdown = Shutdown::new();
+        let drain = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drain(Duration::from_secs(10)).await }
+        });
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(!shutdown.is_ready());
+        assert!(!drain.is_finished());
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        drain.await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), shutdown.stopped()).await.unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+use opentelemetry::{
+    global,
+    propagation::Extractor,
+    trace::TraceError,
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+
+use crate::{
+    error::{RateLimitError, Result},
+    settings::Settings,
+};
+
+/// OpenTelemetry tracing configuration
+#[derive(Debug, Clone)]
+pub struct TracingConfig {
+    /// OTLP gRPC collector endpoint; tracing export is disabled when unset
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "ratelimit".to_string(),
+        }
+    }
+}
+
+impl TracingConfig {
+    /// The tracing configuration given by the settings
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            otlp_endpoint: settings.otel_exporter_otlp_endpoint.clone(),
+            service_name: settings.otel_service_name.clone(),
+        }
+    }
+}
+
+/// Install the global OTLP tracer provider and W3C trace context propagator.
+/// Returns `None` when no endpoint is configured.
+pub fn init_tracer(config: &TracingConfig) -> Result<Option<trace::Tracer>> {
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint.clone()),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )])))
+        .install_batch(runtime::Tokio)
+        .map(Some)
+        .map_err(|e: TraceError| {
+            RateLimitError::Service(format!("Failed to initialize OTLP tracing: {}", e))
+        })
+}
+
+/// Flush pending spans and shut down the global tracer provider
+pub fn shutdown_tracer() {
+    global::shutdown_tracer_provider();
+}
+
+/// Extract the W3C trace context propagated in gRPC request metadata
+pub fn extract_context(metadata: &tonic::metadata::MetadataMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&MetadataExtractor(metadata)))
+}
+
+struct MetadataExtractor<'a>(&'a tonic::metadata::MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|key| match key {
+                tonic::metadata::KeyRef::Ascii(key) => Some(key.as_str()),
+                tonic::metadata::KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+
+    #[test]
+    fn test_tracing_disabled_without_endpoint() {
+        let tracer = init_tracer(&TracingConfig::default()).unwrap();
+        assert!(tracer.is_none());
+    }
+
+    #[test]
+    fn test_extract_w3c_context() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let mut metadata = tonic::metadata::MetadataMap::new();
+        metadata.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+        );
+
+        let context = extract_context(&metadata);
+        let span_context = context.span().span_context().clone();
+        assert!(span_context.is_remote());
+        assert_eq!(span_context.trace_id().to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(span_context.span_id().to_string(), "00f067aa0ba902b7");
+    }
+}
@@ -1,33 +1,76 @@
-use chrono::{DateTime, Utc};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-/// Time utilities for rate limiting calculations
-pub struct TimeSource {
-    _private: (),
+/// Source of the current time for rate limiting calculations
+pub trait TimeSource: Send + Sync {
+    /// Get the current time as a DateTime<Utc>
+    fn utc_now(&self) -> DateTime<Utc>;
+
+    /// Get the current Unix timestamp in seconds
+    fn unix_now(&self) -> i64 {
+        self.utc_now().timestamp()
+    }
 }
 
-impl TimeSource {
+/// Time source backed by the system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTimeSource;
+
+impl SystemTimeSource {
     pub fn new() -> Self {
-        Self { _private: () }
+        Self
     }
+}
 
-    /// Get the current Unix timestamp in seconds
-    pub fn unix_now(&self) -> i64 {
+impl TimeSource for SystemTimeSource {
+    fn utc_now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn unix_now(&self) -> i64 {
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards")
             .as_secs() as i64
     }
+}
 
-    /// Get the current time as a DateTime<Utc>
-    pub fn utc_now(&self) -> DateTime<Utc> {
-        Utc::now()
+/// Manually driven time source for deterministic tests
+#[derive(Debug)]
+pub struct MockTimeSource {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl MockTimeSource {
+    /// Create a mock clock frozen at `now`
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: Mutex::new(now) }
+    }
+
+    /// Create a mock clock frozen at the given Unix timestamp in seconds
+    pub fn from_unix(seconds: i64) -> Self {
+        Self::new(DateTime::from_timestamp(seconds, 0).expect("timestamp out of range"))
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("mock clock lock poisoned");
+        *now += chrono::Duration::from_std(duration).expect("duration out of range");
+    }
+
+    /// Set the clock to `now`
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("mock clock lock poisoned") = now;
     }
 }
 
-impl Default for TimeSource {
-    fn default() -> Self {
-        Self::new()
+impl TimeSource for MockTimeSource {
+    fn utc_now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("mock clock lock poisoned")
     }
 }
 
@@ -38,6 +81,9 @@ pub enum Unit {
     Minute,
     Hour,
     Day,
+    Week,
+    /// Calendar month; `to_seconds` reports a nominal 30 days
+    Month,
 }
 
 impl Unit {
@@ -48,6 +94,8 @@ impl Unit {
             Unit::Minute => 60,
             Unit::Hour => 3600,
             Unit::Day => 86400,
+            Unit::Week => 7 * 86400,
+            Unit::Month => 30 * 86400,
         }
     }
 
@@ -60,32 +108,186 @@ impl Unit {
     pub fn is_per_second(self) -> bool {
         matches!(self, Unit::Second)
     }
+
+    /// Check if windows of this unit can be aligned to the calendar
+    pub fn supports_calendar(self) -> bool {
+        matches!(self, Unit::Day | Unit::Week | Unit::Month)
+    }
+
+    /// Lowercase name of the unit, as written in configuration
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unit::Second => "second",
+            Unit::Minute => "minute",
+            Unit::Hour => "hour",
+            Unit::Day => "day",
+            Unit::Week => "week",
+            Unit::Month => "month",
+        }
+    }
+}
+
+/// A rate limit window: a base unit scaled by a multiplier (e.g. 15 seconds, 7 days),
+/// or a calendar day/week/month starting at local midnight in `timezone`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub unit: Unit,
+    pub multiplier: u32,
+    pub timezone: Option<Tz>,
 }
 
-// Proto conversion implementations would go here when using actual protobuf generation
-// For now, we'll use simple integer mappings in the service layer
+impl Window {
+    /// Create a window spanning `multiplier` units; a multiplier of 0 is treated as 1
+    pub fn new(unit: Unit, multiplier: u32) -> Self {
+        Self {
+            unit,
+            multiplier: multiplier.max(1),
+            timezone: None,
+        }
+    }
+
+    /// Create a calendar-aligned window: days start at local midnight, weeks on
+    /// Monday and months on the 1st, all in the given timezone
+    pub fn calendar(unit: Unit, timezone: Tz) -> Self {
+        Self {
+            unit,
+            multiplier: 1,
+            timezone: Some(timezone),
+        }
+    }
+
+    /// Check if this window follows the calendar rather than fixed-length intervals
+    pub fn is_calendar(self) -> bool {
+        self.timezone.is_some()
+    }
+
+    /// Length of the window in seconds
+    pub fn to_seconds(self) -> u64 {
+        self.unit.to_seconds() * self.multiplier as u64
+    }
+
+    /// Get the divisor for time window calculations
+    pub fn to_divisor(self) -> i64 {
+        self.to_seconds() as i64
+    }
+
+    /// Check if this window is counted on the per-second Redis instance
+    pub fn is_per_second(self) -> bool {
+        self.unit.is_per_second()
+    }
+
+    /// The largest unit that fits in this window, used when reporting to Envoy,
+    /// whose protocol only knows single seconds, minutes, hours and days
+    pub fn envoy_unit(self) -> Unit {
+        let seconds = self.to_seconds();
+        [Unit::Day, Unit::Hour, Unit::Minute]
+            .into_iter()
+            .find(|unit| unit.to_seconds() <= seconds)
+            .unwrap_or(Unit::Second)
+    }
+
+    /// Cache key component distinguishing this window from a plain single-unit
+    /// window, whose keys carry no tag; `None` for plain windows
+    pub fn key_tag(self) -> Option<String> {
+        match self.timezone {
+            Some(tz) => Some(format!("{}@{}", self.unit.as_str(), tz.name())),
+            None if self.multiplier > 1 => Some(format!("{}s", self.to_seconds())),
+            None => None,
+        }
+    }
+
+    /// Index of the window containing `now`; all requests in one window share it
+    pub fn index_at(self, now: DateTime<Utc>) -> i64 {
+        match self.timezone {
+            Some(tz) => calendar_index(self.unit, now.with_timezone(&tz).date_naive()),
+            None => now.timestamp().div_euclid(self.to_divisor()),
+        }
+    }
+
+    /// Time remaining from `now` until the window resets
+    pub fn reset_after(self, now: DateTime<Utc>) -> Duration {
+        let next_window_start = match self.timezone {
+            Some(tz) => {
+                let today = now.with_timezone(&tz).date_naive();
+                local_midnight(tz, next_calendar_start(self.unit, today))
+            }
+            None => (self.index_at(now) + 1) * self.to_divisor(),
+        };
+
+        Duration::from_secs((next_window_start - now.timestamp()).max(0) as u64)
+    }
+
+    /// Seconds a counter for the window containing `now` must be kept for
+    pub fn expire_seconds(self, now: DateTime<Utc>) -> u64 {
+        if self.is_calendar() {
+            self.reset_after(now).as_secs().max(1)
+        } else {
+            self.to_seconds()
+        }
+    }
+}
+
+/// Calendar window index of a local date: days or ISO weeks since the epoch, or months
+fn calendar_index(unit: Unit, date: NaiveDate) -> i64 {
+    let days = date.signed_duration_since(NaiveDate::default()).num_days();
+    match unit {
+        // 1970-01-01 was a Thursday, so shift weeks to start on Monday
+        Unit::Week => (days + 3).div_euclid(7),
+        Unit::Month => (date.year() as i64 - 1970) * 12 + date.month0() as i64,
+        _ => days,
+    }
+}
+
+/// First local date of the calendar window following the one containing `date`
+fn next_calendar_start(unit: Unit, date: NaiveDate) -> NaiveDate {
+    match unit {
+        Unit::Week => date + Days::new(7 - date.weekday().num_days_from_monday() as u64),
+        Unit::Month => {
+            let (year, month) = if date.month() == 12 {
+                (date.year() + 1, 1)
+            } else {
+                (date.year(), date.month() + 1)
+            };
+            NaiveDate::from_ymd_opt(year, month, 1).expect("first of month is always valid")
+        }
+        _ => date + Days::new(1),
+    }
+}
+
+/// Unix timestamp of local midnight on `date`, or of the first valid local time
+/// after it when a DST transition skips midnight
+fn local_midnight(tz: Tz, date: NaiveDate) -> i64 {
+    let midnight = date.and_time(NaiveTime::MIN);
+    tz.from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(midnight + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .map(|start| start.timestamp())
+        .unwrap_or_else(|| midnight.and_utc().timestamp())
+}
+
+impl From<Unit> for Window {
+    fn from(unit: Unit) -> Self {
+        Self::new(unit, 1)
+    }
+}
 
 /// Calculate when the rate limit window will reset
-pub fn calculate_reset(unit: &Unit, time_source: &TimeSource) -> Duration {
-    let now = time_source.unix_now();
-    let window_size = unit.to_divisor();
-    let current_window = now / window_size;
-    let next_window_start = (current_window + 1) * window_size;
-    let seconds_until_reset = next_window_start - now;
-    
-    Duration::from_secs(seconds_until_reset as u64)
+pub fn calculate_reset(window: impl Into<Window>, time_source: &dyn TimeSource) -> Duration {
+    window.into().reset_after(time_source.utc_now())
 }
 
 /// Generate cache key for a rate limit
 pub fn generate_cache_key(
     domain: &str,
     descriptors: &[(&str, &str)],
-    unit: Unit,
-    time_source: &TimeSource,
+    window: impl Into<Window>,
+    time_source: &dyn TimeSource,
 ) -> String {
-    let now = time_source.unix_now();
-    let window_size = unit.to_divisor();
-    let current_window = now / window_size;
+    let window = window.into();
+    let current_window = window.index_at(time_source.utc_now());
 
     let mut key_parts = vec![domain.to_string()];
     
@@ -96,7 +298,10 @@ pub fn generate_cache_key(
             key_parts.push(format!("{}_{}", key, value));
         }
     }
-    
+
+    if let Some(tag) = window.key_tag() {
+        key_parts.push(tag);
+    }
     key_parts.push(current_window.to_string());
     
     key_parts.join(":")
@@ -121,13 +326,121 @@ mod tests {
 
     #[test]
     fn test_cache_key_generation() {
-        let time_source = TimeSource::new();
+        let time_source = SystemTimeSource::new();
         let descriptors = vec![("database", "users"), ("action", "read")];
         
         let key = generate_cache_key("mongo", &descriptors, Unit::Second, &time_source);
         assert!(key.starts_with("mongo:database_users:action_read:"));
     }
 
+    #[test]
+    fn test_window_multiplier() {
+        let window = Window::new(Unit::Second, 15);
+        assert_eq!(window.to_seconds(), 15);
+        assert_eq!(window.envoy_unit(), Unit::Second);
+        assert!(window.is_per_second());
+
+        assert_eq!(Window::new(Unit::Second, 120).envoy_unit(), Unit::Minute);
+        assert_eq!(Window::new(Unit::Day, 7).to_seconds(), 7 * 86400);
+        assert_eq!(Window::new(Unit::Day, 7).envoy_unit(), Unit::Day);
+        assert_eq!(Window::new(Unit::Minute, 0), Window::from(Unit::Minute));
+    }
+
+    #[test]
+    fn test_calculate_reset_within_window() {
+        let time_source = MockTimeSource::from_unix(1_700_000_015);
+        let window = Window::new(Unit::Second, 15);
+        assert_eq!(calculate_reset(window, &time_source), Duration::from_secs(10));
+
+        time_source.advance(Duration::from_secs(9));
+        assert_eq!(calculate_reset(window, &time_source), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_mock_time_source_crosses_window() {
+        let time_source = MockTimeSource::from_unix(1_700_000_080);
+        let descriptors = vec![("api", "search")];
+
+        let first = generate_cache_key("domain", &descriptors, Unit::Minute, &time_source);
+        time_source.advance(Duration::from_secs(19));
+        assert_eq!(first, generate_cache_key("domain", &descriptors, Unit::Minute, &time_source));
+
+        time_source.advance(Duration::from_secs(1));
+        assert_ne!(first, generate_cache_key("domain", &descriptors, Unit::Minute, &time_source));
+        assert_eq!(time_source.unix_now(), 1_700_000_100);
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_calendar_day_in_timezone() {
+        let window = Window::calendar(Unit::Day, chrono_tz::America::New_York);
+
+        // 03:30 UTC is still the previous day in New York (UTC-5 in January)
+        let before = utc(2024, 1, 10, 3, 30);
+        let after = utc(2024, 1, 10, 5, 30);
+        assert_ne!(window.index_at(before), window.index_at(after));
+        assert_eq!(window.reset_after(before), Duration::from_secs(90 * 60));
+        assert_eq!(window.reset_after(after), Duration::from_secs(23 * 3600 + 30 * 60));
+    }
+
+    #[test]
+    fn test_calendar_day_across_dst() {
+        let window = Window::calendar(Unit::Day, chrono_tz::America::New_York);
+
+        // 2024-03-10 is only 23 hours long in New York
+        let midnight = utc(2024, 3, 10, 5, 0);
+        assert_eq!(window.reset_after(midnight), Duration::from_secs(23 * 3600));
+        assert_eq!(window.expire_seconds(midnight), 23 * 3600);
+    }
+
+    #[test]
+    fn test_calendar_week_starts_monday() {
+        let window = Window::calendar(Unit::Week, chrono_tz::UTC);
+
+        // 2024-01-14 is a Sunday, 2024-01-15 a Monday
+        let sunday = utc(2024, 1, 14, 12, 0);
+        let monday = utc(2024, 1, 15, 0, 0);
+        assert_ne!(window.index_at(sunday), window.index_at(monday));
+        assert_eq!(window.index_at(monday), window.index_at(utc(2024, 1, 21, 23, 59)));
+        assert_eq!(window.reset_after(sunday), Duration::from_secs(12 * 3600));
+    }
+
+    #[test]
+    fn test_calendar_month() {
+        let window = Window::calendar(Unit::Month, chrono_tz::Europe::Berlin);
+
+        // Berlin is UTC+1 in winter, so February starts at 23:00 UTC on Jan 31
+        let end_of_january = utc(2024, 1, 31, 22, 0);
+        let start_of_february = utc(2024, 1, 31, 23, 0);
+        assert_ne!(window.index_at(end_of_january), window.index_at(start_of_february));
+        assert_eq!(window.reset_after(end_of_january), Duration::from_secs(3600));
+
+        // Leap year February has 29 days
+        assert_eq!(window.reset_after(start_of_february), Duration::from_secs(29 * 86400));
+    }
+
+    #[test]
+    fn test_cache_key_window_tags() {
+        let time_source = MockTimeSource::from_unix(1_700_000_000);
+        let descriptors = vec![("tenant", "acme")];
+
+        let plain = generate_cache_key("d", &descriptors, Unit::Day, &time_source);
+        let fixed = generate_cache_key("d", &descriptors, Window::new(Unit::Day, 7), &time_source);
+        let calendar = generate_cache_key(
+            "d",
+            &descriptors,
+            Window::calendar(Unit::Day, chrono_tz::Asia::Tokyo),
+            &time_source,
+        );
+
+        assert_eq!(plain, "d:tenant_acme:19675");
+        assert_eq!(fixed, "d:tenant_acme:604800s:2810");
+        assert_eq!(calendar, "d:tenant_acme:day@Asia/Tokyo:19676");
+    }
+
     #[test]
     fn test_hits_addend() {
         assert_eq!(get_hits_addend(0), 1);
@@ -0,0 +1,372 @@
+use prost::Message;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Endpoint;
+use tracing::{info, warn};
+
+use crate::{
+    config::{RateLimit, RateLimitConfig, RateLimitDescriptor, RateLimitUnit},
+    error::{RateLimitError, Result},
+    settings::Settings,
+    proto::generated::{
+        envoy::{
+            config::core::v3::Node,
+            service::discovery::v3::{
+                aggregated_discovery_service_client::AggregatedDiscoveryServiceClient, DiscoveryRequest,
+                DiscoveryResponse,
+            },
+        },
+        google::rpc::Status,
+        ratelimit::config::v3 as pb,
+    },
+    provider::{compile_snapshot, ConfigProvider, ConfigSnapshot, ConfigSnapshots},
+};
+
+/// Type URL of the `RateLimitConfig` resources requested from the management server
+pub const RATE_LIMIT_CONFIG_TYPE_URL: &str = "type.googleapis.com/ratelimit.config.v3.RateLimitConfig";
+
+/// Default wait before reconnecting to the management server
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(3);
+
+/// xDS config provider configuration
+#[derive(Debug, Clone)]
+pub struct XdsConfig {
+    /// Management server address, e.g. `http://xds:18000`
+    pub server_url: String,
+    /// Node id the management server selects configs by
+    pub node_id: String,
+    /// Node cluster, sent when not empty
+    pub node_cluster: String,
+    /// Wait before reconnecting after the stream fails or closes
+    pub retry_interval: Duration,
+}
+
+impl XdsConfig {
+    /// Configuration for a management server, with defaults for the rest.
+    /// Addresses without a scheme are reached over plaintext HTTP/2.
+    pub fn new(server_url: impl Into<String>) -> Self {
+        let server_url = server_url.into();
+        let server_url = if server_url.contains("://") {
+            server_url
+        } else {
+            format!("http://{}", server_url)
+        };
+        Self {
+            server_url,
+            node_id: "default".to_string(),
+            node_cluster: String::new(),
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+        }
+    }
+
+    /// The xDS configuration given by the settings; `None` when no server is configured
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        let server_url = settings.config_grpc_xds_server_url.clone()?;
+        Some(Self {
+            node_id: settings.config_grpc_xds_node_id.clone(),
+            node_cluster: settings.config_grpc_xds_node_cluster.clone(),
+            retry_interval: Duration::from_secs(settings.config_grpc_xds_retry_secs),
+            ..Self::new(server_url)
+        })
+    }
+}
+
+/// Convert a `ratelimit.config.v3.RateLimitConfig` resource
+pub fn config_from_proto(config: pb::RateLimitConfig) -> Result<RateLimitConfig> {
+    if config.domain.is_empty() {
+        return Err(RateLimitError::Config(format!(
+            "xDS config '{}' has no domain",
+            config.name
+        )));
+    }
+    Ok(RateLimitConfig {
+        domain: config.domain,
+        descriptors: config
+            .descriptors
+            .into_iter()
+            .map(descriptor_from_proto)
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn descriptor_from_proto(descriptor: pb::RateLimitDescriptor) -> Result<RateLimitDescriptor> {
+    if descriptor.key.is_empty() {
+        return Err(RateLimitError::Config("xDS descriptor has no key".to_string()));
+    }
+    let rate_limit = descriptor
+        .rate_limit
+        .map(|rate_limit| rate_limit_from_proto(rate_limit, &descriptor.key))
+        .transpose()?;
+    let descriptors = descriptor
+        .descriptors
+        .into_iter()
+        .map(descriptor_from_proto)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(RateLimitDescriptor {
+        key: descriptor.key,
+        value: Some(descriptor.value).filter(|value| !value.is_empty()),
+        value_prefix: None,
+        value_regex: None,
+        value_cidr: None,
+        share_threshold: None,
+        detailed_metric: None,
+        rate_limit,
+        rate_limits: None,
+        shadow_mode: descriptor.shadow_mode.then_some(true),
+        descriptors: Some(descriptors).filter(|descriptors| !descriptors.is_empty()),
+    })
+}
+
+fn rate_limit_from_proto(rate_limit: pb::RateLimit, key: &str) -> Result<RateLimit> {
+    use pb::rate_limit::Unit;
+
+    let unit = match Unit::try_from(rate_limit.unit) {
+        Ok(Unit::Second) => RateLimitUnit::Second,
+        Ok(Unit::Minute) => RateLimitUnit::Minute,
+        Ok(Unit::Hour) => RateLimitUnit::Hour,
+        Ok(Unit::Day) => RateLimitUnit::Day,
+        // The window of an unlimited rule is never used
+        Ok(Unit::Unknown) if rate_limit.unlimited => RateLimitUnit::Second,
+        _ => {
+            return Err(RateLimitError::Config(format!(
+                "xDS rate limit for descriptor '{}' has unknown unit {}",
+                key, rate_limit.unit
+            )))
+        }
+    };
+    Ok(RateLimit {
+        requests_per_unit: rate_limit.requests_per_unit,
+        unit,
+        unit_multiplier: None,
+        calendar: None,
+        timezone: None,
+        unlimited: rate_limit.unlimited.then_some(true),
+        name: None,
+    })
+}
+
+/// Decode, convert and compile every resource of a response. The response is
+/// rejected as a whole if any resource is invalid or two share a domain.
+pub fn compile_resources(resources: &[prost_types::Any]) -> Result<ConfigSnapshot> {
+    let configs = resources
+        .iter()
+        .map(|resource| {
+            if resource.type_url != RATE_LIMIT_CONFIG_TYPE_URL {
+                return Err(RateLimitError::Config(format!(
+                    "unexpected xDS resource type '{}'",
+                    resource.type_url
+                )));
+            }
+            let config = pb::RateLimitConfig::decode(resource.value.as_slice())
+                .map_err(|e| RateLimitError::Config(format!("Failed to decode xDS resource: {}", e)))?;
+            config_from_proto(config)
+        })
+        .collect::<Result<_>>()?;
+    compile_snapshot(configs)
+}
+
+/// Subscribes to `RateLimitConfig` resources over ADS (state of the world). Every
+/// response holds all the domains the management server provides, and is sent as
+/// one snapshot once it compiles; responses that do not are NACKed and reported as
+/// failed reloads.
+pub struct XdsConfigProvider {
+    config: XdsConfig,
+}
+
+impl XdsConfigProvider {
+    pub fn new(config: XdsConfig) -> Self {
+        Self { config }
+    }
+
+    /// Stay subscribed until the snapshot stream is dropped, reconnecting after
+    /// `retry_interval` whenever the stream fails or is closed by the server
+    async fn run(self, snapshots: mpsc::Sender<Result<ConfigSnapshot>>) {
+        // Version of the last accepted response, sent back on every request
+        let mut version = String::new();
+        loop {
+            let result = tokio::select! {
+                result = self.subscribe(&mut version, &snapshots) => result,
+                _ = snapshots.closed() => return,
+            };
+            match result {
+                Ok(()) => warn!("xDS stream to {} closed", self.config.server_url),
+                Err(e) => warn!("xDS stream to {} failed: {}", self.config.server_url, e),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.retry_interval) => {}
+                _ = snapshots.closed() => return,
+            }
+        }
+    }
+
+    /// Open a stream and process responses until it ends
+    async fn subscribe(&self, version: &mut String, snapshots: &mpsc::Sender<Result<ConfigSnapshot>>) -> Result<()> {
+        let channel = Endpoint::from_shared(self.config.server_url.clone())
+            .map_err(|e| RateLimitError::Config(format!("Invalid xDS server URL: {}", e)))?
+            .connect()
+            .await
+            .map_err(|e| RateLimitError::Service(format!("Failed to connect: {}", e)))?;
+        let mut client = AggregatedDiscoveryServiceClient::new(channel);
+
+        // The first request resumes from the last accepted version, if any
+        let (requests, outgoing) = mpsc::channel(1);
+        requests
+            .try_send(self.request(version, String::new(), None))
+            .expect("channel has room for the first request");
+        let mut responses = client
+            .stream_aggregated_resources(ReceiverStream::new(outgoing))
+            .await
+            .map_err(Box::new)?
+            .into_inner();
+        info!(
+            "Subscribed to rate limit configs at {} as node {}",
+            self.config.server_url, self.config.node_id
+        );
+
+        while let Some(response) = responses.message().await.map_err(Box::new)? {
+            let (snapshot, error_detail) = match compile_response(&response) {
+                Ok(snapshot) => {
+                    info!("Accepted xDS config version {}", response.version_info);
+                    *version = response.version_info.clone();
+                    (Ok(snapshot), None)
+                }
+                Err(e) => {
+                    info!("Rejecting xDS config version {}", response.version_info);
+                    let error_detail = Status {
+                        code: tonic::Code::InvalidArgument as i32,
+                        message: e.to_string(),
+                        details: Vec::new(),
+                    };
+                    (Err(e), Some(error_detail))
+                }
+            };
+            if snapshots.send(snapshot).await.is_err() {
+                break;
+            }
+            let request = self.request(version, response.nonce, error_detail);
+            if requests.send(request).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// A request ACKing (no error) or NACKing the response with `nonce`
+    fn request(&self, version: &str, nonce: String, error_detail: Option<Status>) -> DiscoveryRequest {
+        DiscoveryRequest {
+            version_info: version.to_string(),
+            node: Some(Node {
+                id: self.config.node_id.clone(),
+                cluster: self.config.node_cluster.clone(),
+                metadata: None,
+                user_agent_name: env!("CARGO_PKG_NAME").to_string(),
+            }),
+            resource_names: Vec::new(),
+            type_url: RATE_LIMIT_CONFIG_TYPE_URL.to_string(),
+            response_nonce: nonce,
+            error_detail,
+        }
+    }
+}
+
+impl ConfigProvider for XdsConfigProvider {
+    fn name(&self) -> String {
+        format!("xDS server {}", self.config.server_url)
+    }
+
+    // The server may not be reachable yet; configs arrive once it is
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn snapshots(self: Box<Self>) -> ConfigSnapshots {
+        let (snapshots, receiver) = mpsc::channel(1);
+        tokio::spawn((*self).run(snapshots));
+        Box::pin(ReceiverStream::new(receiver))
+    }
+}
+
+fn compile_response(response: &DiscoveryResponse) -> Result<ConfigSnapshot> {
+    if response.type_url != RATE_LIMIT_CONFIG_TYPE_URL {
+        return Err(RateLimitError::Config(format!(
+            "unexpected xDS response type '{}'",
+            response.type_url
+        )));
+    }
+    compile_resources(&response.resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(config: &pb::RateLimitConfig) -> prost_types::Any {
+        prost_types::Any {
+            type_url: RATE_LIMIT_CONFIG_TYPE_URL.to_string(),
+            value: config.encode_to_vec(),
+        }
+    }
+
+    fn proto_config(domain: &str, unit: pb::rate_limit::Unit) -> pb::RateLimitConfig {
+        pb::RateLimitConfig {
+            name: domain.to_string(),
+            domain: domain.to_string(),
+            descriptors: vec![pb::RateLimitDescriptor {
+                key: "user".to_string(),
+                value: String::new(),
+                rate_limit: None,
+                shadow_mode: false,
+                descriptors: vec![pb::RateLimitDescriptor {
+                    key: "path".to_string(),
+                    value: "/upload".to_string(),
+                    rate_limit: Some(pb::RateLimit {
+                        requests_per_unit: 10,
+                        unit: unit as i32,
+                        unlimited: false,
+                    }),
+                    shadow_mode: true,
+                    descriptors: Vec::new(),
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_config_from_proto() {
+        let config = config_from_proto(proto_config("api", pb::rate_limit::Unit::Hour)).unwrap();
+        assert_eq!(config.domain, "api");
+        let user = &config.descriptors[0];
+        assert_eq!(user.value, None);
+        assert!(user.rate_limit.is_none());
+        let path = &user.descriptors.as_ref().unwrap()[0];
+        assert_eq!(path.value.as_deref(), Some("/upload"));
+        assert_eq!(path.shadow_mode, Some(true));
+        let rate_limit = path.rate_limit.as_ref().unwrap();
+        assert_eq!(rate_limit.requests_per_unit, 10);
+        assert!(matches!(rate_limit.unit, RateLimitUnit::Hour));
+        assert_eq!(rate_limit.unlimited, None);
+    }
+
+    #[test]
+    fn test_compile_resources_rejects_invalid() {
+        let api = resource(&proto_config("api", pb::rate_limit::Unit::Minute));
+        let compiled = compile_resources(std::slice::from_ref(&api)).unwrap();
+        assert_eq!(compiled[0].domain(), "api");
+
+        let unknown_unit = resource(&proto_config("web", pb::rate_limit::Unit::Unknown));
+        let err = compile_resources(&[api.clone(), unknown_unit]).unwrap_err();
+        assert!(err.to_string().contains("unknown unit"));
+
+        let err = compile_resources(&[api.clone(), api.clone()]).unwrap_err();
+        assert!(err.to_string().contains("duplicate config for domain 'api'"));
+
+        let wrong_type = prost_types::Any {
+            type_url: "type.googleapis.com/envoy.config.cluster.v3.Cluster".to_string(),
+            value: Vec::new(),
+        };
+        assert!(compile_resources(&[wrong_type]).is_err());
+    }
+}
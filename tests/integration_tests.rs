@@ -1,11 +1,10 @@
-use rust_ratelimit::{
-    cache::{RedisRateLimitCache, RateLimitDescriptor, RateLimitRequest, ResponseCode},
-    config::{CompiledRateLimitConfig, RateLimit, RateLimitConfig, RateLimitDescriptor as ConfigDescriptor, RateLimitUnit},
-    limiter::RateLimiter,
-    redis::{RedisClientPool, RedisConfig},
+use rust_ratelimit::config::{
+    CompiledRateLimitConfig, RateLimit, RateLimitConfig, RateLimitDescriptor as ConfigDescriptor,
+    RateLimitUnit,
+};
+use rust_ratelimit::proto::generated::envoy::service::discovery::v3::{
+    aggregated_discovery_service_server::AggregatedDiscoveryService, DiscoveryRequest, DiscoveryResponse,
 };
-use std::time::Duration;
-use tokio::time::sleep;
 
 #[tokio::test]
 async fn test_basic_rate_limiting() {
@@ -18,24 +17,42 @@ async fn test_basic_rate_limiting() {
             ConfigDescriptor {
                 key: "database".to_string(),
                 value: Some("users".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 5,
                     unit: RateLimitUnit::Second,
+                    unit_multiplier: None,
+                    calendar: None,
+                    timezone: None,
                     unlimited: None,
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: None,
             },
             ConfigDescriptor {
                 key: "api".to_string(),
                 value: Some("read".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 100,
                     unit: RateLimitUnit::Minute,
+                    unit_multiplier: None,
+                    calendar: None,
+                    timezone: None,
                     unlimited: None,
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: None,
             },
@@ -66,18 +83,33 @@ async fn test_nested_descriptors() {
             ConfigDescriptor {
                 key: "message_type".to_string(),
                 value: Some("marketing".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: None,
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: Some(vec![
                     ConfigDescriptor {
                         key: "to_number".to_string(),
                         value: None, // Match any value
+                        value_prefix: None,
+                        value_regex: None,
+                        value_cidr: None,
+                        share_threshold: None,
+                        detailed_metric: None,
                         rate_limit: Some(RateLimit {
                             requests_per_unit: 5,
                             unit: RateLimitUnit::Day,
+                            unit_multiplier: None,
+                            calendar: None,
+                            timezone: None,
                             unlimited: None,
                             name: None,
                         }),
+                        rate_limits: None,
                         shadow_mode: None,
                         descriptors: None,
                     }
@@ -86,12 +118,21 @@ async fn test_nested_descriptors() {
             ConfigDescriptor {
                 key: "to_number".to_string(),
                 value: None,
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 100,
                     unit: RateLimitUnit::Day,
+                    unit_multiplier: None,
+                    calendar: None,
+                    timezone: None,
                     unlimited: None,
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: None,
             },
@@ -119,12 +160,21 @@ async fn test_shadow_mode() {
             ConfigDescriptor {
                 key: "user".to_string(),
                 value: Some("test_user".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 1,
                     unit: RateLimitUnit::Second,
+                    unit_multiplier: None,
+                    calendar: None,
+                    timezone: None,
                     unlimited: None,
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: Some(true),
                 descriptors: None,
             },
@@ -146,12 +196,21 @@ async fn test_unlimited_rate_limit() {
             ConfigDescriptor {
                 key: "service".to_string(),
                 value: Some("health_check".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 0, // Not used for unlimited
                     unit: RateLimitUnit::Second,
+                    unit_multiplier: None,
+                    calendar: None,
+                    timezone: None,
                     unlimited: Some(true),
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: None,
             },
@@ -169,9 +228,9 @@ async fn test_unlimited_rate_limit() {
 #[tokio::test]
 async fn test_cache_key_generation() {
     // Test cache key generation without requiring Redis
-    use rust_ratelimit::utils::{generate_cache_key, TimeSource, Unit};
+    use rust_ratelimit::utils::{generate_cache_key, SystemTimeSource, Unit};
     
-    let time_source = TimeSource::new();
+    let time_source = SystemTimeSource::new();
     let descriptors = vec![("database", "users"), ("action", "read")];
     
     let key = generate_cache_key("test_domain", &descriptors, Unit::Second, &time_source);
@@ -182,9 +241,9 @@ async fn test_cache_key_generation() {
 
 #[tokio::test]
 async fn test_time_windows() {
-    use rust_ratelimit::utils::{TimeSource, Unit, generate_cache_key};
+    use rust_ratelimit::utils::{SystemTimeSource, Unit, generate_cache_key};
     
-    let time_source = TimeSource::new();
+    let time_source = SystemTimeSource::new();
     let descriptors = vec![("test", "key")];
     
     // Generate keys for different units
@@ -213,24 +272,42 @@ async fn test_hits_addend() {
     assert_eq!(get_hits_addend(100), 100);
 }
 
-// Example test showing how the system would work with actual Redis
-// This would require testcontainers or a running Redis instance
-/*
-#[tokio::test]
-async fn test_redis_rate_limiting() {
-    // Start Redis container
-    let docker = testcontainers::clients::Cli::default();
-    let redis_container = docker.run(testcontainers::images::redis::Redis::default());
-    let redis_port = redis_container.get_host_port_ipv4(6379);
+// Redis-backed limiter driven by a mock clock, so window boundaries can be
+// crossed without real sleeping. Requires a Redis instance at REDIS_URL
+// (default redis://localhost:6379).
+async fn create_redis_limiter(
+    clock: std::sync::Arc<rust_ratelimit::utils::MockTimeSource>,
+) -> rust_ratelimit::limiter::RateLimiter {
+    use rust_ratelimit::{
+        cache::RedisRateLimitCache,
+        limiter::RateLimiter,
+        redis::{RedisClientPool, RedisConfig},
+        utils::{SystemTimeSource, TimeSource},
+    };
 
     let redis_config = RedisConfig {
-        url: format!("redis://localhost:{}", redis_port),
+        url: std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string()),
         ..Default::default()
     };
 
+    // Counters live in a shared Redis, so keep keys unique per run
+    let prefix = format!("test_{}_{}", std::process::id(), SystemTimeSource::new().unix_now());
+
     let redis_pool = RedisClientPool::new_single(redis_config).await.unwrap();
-    let cache = RedisRateLimitCache::new(redis_pool, 1000, 0.8, "test".to_string());
-    let mut limiter = RateLimiter::new(Box::new(cache));
+    let cache = RedisRateLimitCache::new(redis_pool, 1000, 0.8, prefix).with_time_source(clock);
+    RateLimiter::new(Box::new(cache))
+}
+
+#[tokio::test]
+async fn test_redis_rate_limiting() {
+    use rust_ratelimit::{
+        cache::{RateLimitDescriptor, RateLimitRequest, ResponseCode},
+        utils::MockTimeSource,
+    };
+    use std::{sync::Arc, time::Duration};
+
+    let clock = Arc::new(MockTimeSource::from_unix(1_700_000_005));
+    let mut limiter = create_redis_limiter(clock.clone()).await;
 
     // Add configuration
     let config = RateLimitConfig {
@@ -239,12 +316,21 @@ async fn test_redis_rate_limiting() {
             ConfigDescriptor {
                 key: "api".to_string(),
                 value: Some("endpoint".to_string()),
+                value_prefix: None,
+                value_regex: None,
+                value_cidr: None,
+                share_threshold: None,
+                detailed_metric: None,
                 rate_limit: Some(RateLimit {
                     requests_per_unit: 2,
                     unit: RateLimitUnit::Second,
+                    unit_multiplier: Some(10),
+                    calendar: None,
+                    timezone: None,
                     unlimited: None,
                     name: None,
                 }),
+                rate_limits: None,
                 shadow_mode: None,
                 descriptors: None,
             },
@@ -268,20 +354,1460 @@ async fn test_redis_rate_limiting() {
     // First request should be allowed
     let response = limiter.should_rate_limit(&request).await.unwrap();
     assert_eq!(response.overall_code, ResponseCode::Ok);
+    assert_eq!(response.statuses[0].limit_remaining, 1);
+    assert_eq!(response.statuses[0].duration_until_reset_secs, 5);
 
     // Second request should be allowed
+    clock.advance(Duration::from_secs(4));
     let response = limiter.should_rate_limit(&request).await.unwrap();
     assert_eq!(response.overall_code, ResponseCode::Ok);
+    assert_eq!(response.statuses[0].duration_until_reset_secs, 1);
 
     // Third request should be over limit
     let response = limiter.should_rate_limit(&request).await.unwrap();
     assert_eq!(response.overall_code, ResponseCode::OverLimit);
 
-    // Wait for window to reset
-    sleep(Duration::from_secs(2)).await;
+    // Cross into the next window
+    clock.advance(Duration::from_secs(1));
 
     // Request should be allowed again
     let response = limiter.should_rate_limit(&request).await.unwrap();
     assert_eq!(response.overall_code, ResponseCode::Ok);
+    assert_eq!(response.statuses[0].duration_until_reset_secs, 10);
+}
+
+#[tokio::test]
+async fn test_redis_multiple_limits_per_descriptor() {
+    use rust_ratelimit::{
+        cache::{RateLimitDescriptor, RateLimitRequest, ResponseCode},
+        utils::MockTimeSource,
+    };
+    use std::{sync::Arc, time::Duration};
+
+    // Start on a minute boundary
+    let clock = Arc::new(MockTimeSource::from_unix(1_699_999_980));
+    let mut limiter = create_redis_limiter(clock.clone()).await;
+
+    let yaml = r#"
+domain: multi
+descriptors:
+  - key: api_key
+    value: abc
+    rate_limits:
+      - requests_per_unit: 2
+        unit: second
+      - requests_per_unit: 3
+        unit: minute
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    limiter.add_config(CompiledRateLimitConfig::compile(config).unwrap());
+
+    let request = RateLimitRequest {
+        domain: "multi".to_string(),
+        descriptors: vec![RateLimitDescriptor {
+            entries: vec![("api_key".to_string(), "abc".to_string())],
+        }],
+        hits_addend: 1,
+    };
+
+    // Both limits have room; the per-second limit has fewer hits remaining
+    let response = limiter.should_rate_limit(&request).await.unwrap();
+    assert_eq!(response.overall_code, ResponseCode::Ok);
+    assert_eq!(response.statuses[0].limit_remaining, 1);
+    assert_eq!(response.statuses[0].current_limit.as_ref().unwrap().requests_per_unit, 2);
+
+    // The per-second limit trips first
+    limiter.should_rate_limit(&request).await.unwrap();
+    let response = limiter.should_rate_limit(&request).await.unwrap();
+    assert_eq!(response.overall_code, ResponseCode::OverLimit);
+    assert_eq!(response.statuses[0].current_limit.as_ref().unwrap().requests_per_unit, 2);
+    assert_eq!(response.statuses[0].duration_until_reset_secs, 1);
+
+    // A new second does not help once the per-minute limit is exhausted
+    clock.advance(Duration::from_secs(1));
+    let response = limiter.should_rate_limit(&request).await.unwrap();
+    assert_eq!(response.overall_code, ResponseCode::OverLimit);
+    assert_eq!(response.statuses[0].current_limit.as_ref().unwrap().requests_per_unit, 3);
+    assert_eq!(response.statuses[0].duration_until_reset_secs, 59);
+}
+
+#[tokio::test]
+async fn test_redis_metric_keys() {
+    use rust_ratelimit::{
+        cache::{RateLimitDescriptor, RateLimitRequest},
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+
+    let clock = Arc::new(MockTimeSource::from_unix(1_700_000_000));
+    let mut limiter = create_redis_limiter(clock).await;
+
+    let yaml = r#"
+domain: metrics
+descriptors:
+  - key: api_key
+    value: partner
+    rate_limit:
+      name: partner_quota
+      requests_per_unit: 100
+      unit: minute
+  - key: path
+    value_prefix: /files/
+    detailed_metric: true
+    rate_limit:
+      requests_per_unit: 100
+      unit: minute
+  - key: tenant
+    descriptors:
+      - key: plan
+        rate_limit:
+          requests_per_unit: 100
+          unit: minute
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    limiter.add_config(CompiledRateLimitConfig::compile(config).unwrap());
+
+    let descriptor = |entries: &[(&str, &str)]| RateLimitDescriptor {
+        entries: entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+    };
+    let request = RateLimitRequest {
+        domain: "metrics".to_string(),
+        descriptors: vec![
+            descriptor(&[("api_key", "partner")]),
+            descriptor(&[("path", "/files/report.pdf")]),
+            descriptor(&[("tenant", "acme"), ("plan", "gold")]),
+            descriptor(&[("unknown", "value")]),
+        ],
+        hits_addend: 1,
+    };
+
+    let response = limiter.should_rate_limit(&request).await.unwrap();
+    assert_eq!(
+        response.metric_keys,
+        vec!["partner_quota", "path_/files/report.pdf", "tenant:plan", "no_rule_matched"]
+    );
+}
+
+/// In-process stand-in for an OTLP collector that keeps every exported span
+#[derive(Clone, Default)]
+struct SpanCollector {
+    spans: std::sync::Arc<std::sync::Mutex<Vec<opentelemetry_proto::tonic::trace::v1::Span>>>,
+}
+
+#[otlp_tonic::async_trait]
+impl opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::TraceService for SpanCollector {
+    async fn export(
+        &self,
+        request: otlp_tonic::Request<opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest>,
+    ) -> Result<
+        otlp_tonic::Response<opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceResponse>,
+        otlp_tonic::Status,
+    > {
+        let spans = request
+            .into_inner()
+            .resource_spans
+            .into_iter()
+            .flat_map(|resource| resource.scope_spans)
+            .flat_map(|scope| scope.spans);
+        self.spans.lock().unwrap().extend(spans);
+        Ok(otlp_tonic::Response::new(Default::default()))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_otlp_tracing_spans() {
+    use opentelemetry_proto::tonic::{
+        collector::trace::v1::trace_service_server::TraceServiceServer,
+        common::v1::any_value::Value,
+        trace::v1::Span,
+    };
+    use rust_ratelimit::{
+        cache::{RateLimitDescriptor, RateLimitRequest},
+        telemetry::{self, TracingConfig},
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+    use tracing::Instrument;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    // Start the collector stand-in
+    let collector = SpanCollector::default();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        otlp_tonic::transport::Server::builder()
+            .add_service(TraceServiceServer::new(collector.clone()))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let tracer = telemetry::init_tracer(&TracingConfig {
+        otlp_endpoint: Some(endpoint),
+        service_name: "ratelimit-test".to_string(),
+    })
+    .unwrap()
+    .expect("tracer is installed when an endpoint is set");
+    let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    let guard = tracing::subscriber::set_default(subscriber);
+
+    let mut limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let yaml = r#"
+domain: traced
+descriptors:
+  - key: api_key
+    rate_limit:
+      name: per_key
+      requests_per_unit: 10
+      unit: minute
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    limiter.add_config(CompiledRateLimitConfig::compile(config).unwrap());
+
+    let request = RateLimitRequest {
+        domain: "traced".to_string(),
+        descriptors: vec![RateLimitDescriptor {
+            entries: vec![("api_key".to_string(), "abc".to_string())],
+        }],
+        hits_addend: 1,
+    };
+
+    // Handle the request the way the gRPC handler does, under the caller's trace
+    let mut metadata = tonic::metadata::MetadataMap::new();
+    metadata.insert(
+        "traceparent",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+    );
+    let span = tracing::info_span!("ratelimit.grpc.ShouldRateLimit");
+    span.set_parent(telemetry::extract_context(&metadata));
+    limiter.should_rate_limit(&request).instrument(span).await.unwrap();
+
+    drop(guard);
+    tokio::task::spawn_blocking(telemetry::shutdown_tracer).await.unwrap();
+
+    let spans = collector.spans.lock().unwrap().clone();
+    let find = |name: &str| -> Span {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("span {} was not exported", name))
+            .clone()
+    };
+    let attribute = |span: &Span, key: &str| -> Option<String> {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key == key)
+            .and_then(|kv| kv.value.as_ref()?.value.clone())
+            .map(|value| match value {
+                Value::StringValue(s) => s,
+                Value::IntValue(i) => i.to_string(),
+                other => format!("{:?}", other),
+            })
+    };
+
+    let grpc = find("ratelimit.grpc.ShouldRateLimit");
+    let limiter_span = find("ratelimit.should_rate_limit");
+    let lookup = find("ratelimit.config_lookup");
+    let redis = find("redis.pipeline");
+
+    // Every span belongs to the propagated trace
+    let trace_id = hex_decode("4bf92f3577b34da6a3ce929d0e0e4736");
+    for span in [&grpc, &limiter_span, &lookup, &redis] {
+        assert_eq!(span.trace_id, trace_id, "span {} has the wrong trace", span.name);
+    }
+    assert_eq!(grpc.parent_span_id, hex_decode("00f067aa0ba902b7"));
+    assert_eq!(limiter_span.parent_span_id, grpc.span_id);
+
+    assert_eq!(attribute(&limiter_span, "domain").as_deref(), Some("traced"));
+    assert_eq!(attribute(&limiter_span, "decision").as_deref(), Some("OK"));
+    assert_eq!(attribute(&lookup, "rules").as_deref(), Some("per_key"));
+    assert_eq!(attribute(&redis, "db.system").as_deref(), Some("redis"));
+    assert_eq!(attribute(&redis, "redis.keys").as_deref(), Some("1"));
+}
+
+fn hex_decode(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// In-process stand-in for an OTLP collector that keeps every exported metric
+#[derive(Clone, Default)]
+struct MetricCollector {
+    metrics: std::sync::Arc<std::sync::Mutex<Vec<opentelemetry_proto::tonic::metrics::v1::Metric>>>,
+}
+
+#[otlp_tonic::async_trait]
+impl opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_server::MetricsService
+    for MetricCollector
+{
+    async fn export(
+        &self,
+        request: otlp_tonic::Request<opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest>,
+    ) -> Result<
+        otlp_tonic::Response<opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceResponse>,
+        otlp_tonic::Status,
+    > {
+        let metrics = request
+            .into_inner()
+            .resource_metrics
+            .into_iter()
+            .flat_map(|resource| resource.scope_metrics)
+            .flat_map(|scope| scope.metrics);
+        self.metrics.lock().unwrap().extend(metrics);
+        Ok(otlp_tonic::Response::new(Default::default()))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_otlp_metrics_push() {
+    use opentelemetry_proto::tonic::{
+        collector::metrics::v1::metrics_service_server::MetricsServiceServer,
+        common::v1::any_value::Value,
+        metrics::v1::{metric::Data, number_data_point},
+    };
+    use rust_ratelimit::metrics::{Metrics, MetricsBackend, MetricsConfig};
+    use std::time::Duration;
+
+    let collector = MetricCollector::default();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        otlp_tonic::transport::Server::builder()
+            .add_service(MetricsServiceServer::new(collector.clone()))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let metrics = Metrics::from_config(&MetricsConfig {
+        backend: MetricsBackend::Otlp,
+        otlp_endpoint: endpoint,
+        otlp_interval: Duration::from_secs(3600),
+        ..Default::default()
+    })
+    .unwrap();
+
+    metrics.record_total_request("otlp", "per_key");
+    metrics.record_total_request("otlp", "per_key");
+    metrics.set_redis_connections_active("primary", 1.0);
+    metrics.record_request_duration(0.01);
+
+    let flushed = metrics.clone();
+    tokio::task::spawn_blocking(move || flushed.flush()).await.unwrap().unwrap();
+
+    // Push backends leave the scrape registry empty
+    assert!(metrics.registry().gather().is_empty());
+
+    // The periodic reader may export once on startup; the last export holds the cumulative totals
+    let exported = collector.metrics.lock().unwrap().clone();
+    let find = |name: &str| {
+        exported
+            .iter()
+            .rev()
+            .find(|metric| metric.name == name)
+            .unwrap_or_else(|| panic!("metric {} was not exported", name))
+            .data
+            .clone()
+            .unwrap()
+    };
+
+    let Data::Sum(total) = find("ratelimit_total_requests") else {
+        panic!("total requests should be exported as a sum");
+    };
+    let point = &total.data_points[0];
+    assert_eq!(point.value, Some(number_data_point::Value::AsDouble(2.0)));
+    let labels: Vec<(String, Option<Value>)> = point
+        .attributes
+        .iter()
+        .map(|kv| (kv.key.clone(), kv.value.as_ref().and_then(|v| v.value.clone())))
+        .collect();
+    assert!(labels.contains(&("domain".to_string(), Some(Value::StringValue("otlp".to_string())))));
+    assert!(labels.contains(&("descriptor".to_string(), Some(Value::StringValue("per_key".to_string())))));
+
+    let Data::Gauge(connections) = find("ratelimit_redis_connections_active") else {
+        panic!("active connections should be exported as a gauge");
+    };
+    assert_eq!(connections.data_points[0].value, Some(number_data_point::Value::AsDouble(1.0)));
+
+    let Data::Histogram(duration) = find("ratelimit_request_duration_seconds") else {
+        panic!("request duration should be exported as a histogram");
+    };
+    assert_eq!(duration.data_points[0].count, 1);
+}
+
+#[tokio::test]
+async fn test_redis_near_limit_and_shadow_mode() {
+    use rust_ratelimit::{
+        cache::{RateLimitDescriptor, RateLimitRequest, ResponseCode},
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+
+    let mut limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let yaml = r#"
+domain: flags
+descriptors:
+  - key: api_key
+    value: near
+    rate_limit:
+      requests_per_unit: 4
+      unit: minute
+  - key: api_key
+    value: shadow
+    shadow_mode: true
+    rate_limit:
+      requests_per_unit: 1
+      unit: minute
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    limiter.add_config(CompiledRateLimitConfig::compile(config).unwrap());
+
+    let request = |value: &str| RateLimitRequest {
+        domain: "flags".to_string(),
+        descriptors: vec![RateLimitDescriptor {
+            entries: vec![("api_key".to_string(), value.to_string())],
+        }],
+        hits_addend: 1,
+    };
+
+    // With a 0.8 ratio the 4th hit of 4 is near the limit
+    for _ in 0..3 {
+        let response = limiter.should_rate_limit(&request("near")).await.unwrap();
+        assert!(!response.statuses[0].near_limit);
+    }
+    let response = limiter.should_rate_limit(&request("near")).await.unwrap();
+    assert!(response.statuses[0].near_limit);
+    let response = limiter.should_rate_limit(&request("near")).await.unwrap();
+    assert_eq!(response.overall_code, ResponseCode::OverLimit);
+    assert!(!response.statuses[0].near_limit);
+
+    // Shadow-mode violations are allowed but flagged
+    let response = limiter.should_rate_limit(&request("shadow")).await.unwrap();
+    assert!(!response.statuses[0].shadow_mode);
+    let response = limiter.should_rate_limit(&request("shadow")).await.unwrap();
+    assert_eq!(response.overall_code, ResponseCode::Ok);
+    assert!(response.statuses[0].shadow_mode);
+}
+
+#[tokio::test]
+async fn test_redis_audit_log() {
+    use rust_ratelimit::{
+        audit::{AuditConfig, AuditLog},
+        cache::{RateLimitDescriptor, RateLimitRequest},
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("ratelimit_audit_it_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("audit.log");
+    let audit = AuditLog::new(AuditConfig {
+        path: Some(path.clone()),
+        redact_keys: vec!["user".to_string()],
+        ..Default::default()
+    })
+    .unwrap();
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let audit = Arc::new(audit);
+    let mut limiter = limiter.with_audit_log(audit.clone());
+    let yaml = r#"
+domain: audited
+descriptors:
+  - key: user
+    rate_limits:
+      - name: per_user_minute
+        requests_per_unit: 10
+        unit: minute
+      - name: per_user
+        requests_per_unit: 1
+        unit: hour
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    limiter.add_config(CompiledRateLimitConfig::compile(config).unwrap());
+
+    let request = RateLimitRequest {
+        domain: "audited".to_string(),
+        descriptors: vec![RateLimitDescriptor {
+            entries: vec![("user".to_string(), "alice".to_string())],
+        }],
+        hits_addend: 1,
+    };
+    for _ in 0..3 {
+        limiter.should_rate_limit(&request).await.unwrap();
+    }
+
+    audit.flush();
+
+    // The first request is allowed; the last one is served from the local cache.
+    // The second, hourly limit trips, so the events name it rather than the first
+    let events: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["decision"], "over_limit");
+    assert_eq!(events[0]["domain"], "audited");
+    assert_eq!(events[0]["rule"], "per_user");
+    assert_eq!(events[0]["descriptor"][0]["key"], "user");
+    assert_eq!(events[0]["descriptor"][0]["value"], "[REDACTED]");
+    assert_eq!(events[0]["count"], 2);
+    assert_eq!(events[0]["limit"], 1);
+    assert_eq!(events[0]["window"]["unit"], "hour");
+    assert!(events[1]["count"].is_null());
+    assert_eq!(events[1]["rule"], "per_user");
+}
+
+struct ProbedService;
+
+impl tonic::server::NamedService for ProbedService {
+    const NAME: &'static str = "envoy.service.ratelimit.v3.RateLimitService";
+}
+
+type HealthClient = tonic_health::pb::health_client::HealthClient<tonic::transport::Channel>;
+
+async fn grpc_health_status(
+    client: &mut HealthClient,
+    service: &str,
+) -> tonic_health::pb::health_check_response::ServingStatus {
+    let request = tonic_health::pb::HealthCheckRequest {
+        service: service.to_string(),
+    };
+    client.check(request).await.unwrap().into_inner().status()
+}
+
+async fn wait_for_grpc_health(
+    client: &mut HealthClient,
+    expected: tonic_health::pb::health_check_response::ServingStatus,
+) {
+    use tonic::server::NamedService;
+
+    for _ in 0..40 {
+        if grpc_health_status(client, ProbedService::NAME).await == expected {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+    panic!("gRPC health never reported {:?}", expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_grpc_health_follows_readiness() {
+    use rust_ratelimit::{
+        health, metrics::Metrics, service::RateLimitService, shutdown::Shutdown,
+        utils::MockTimeSource,
+    };
+    use std::{sync::Arc, time::Duration};
+    use tonic_health::pb::health_check_response::ServingStatus;
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let service = Arc::new(RateLimitService::new(limiter, Arc::new(Metrics::new().unwrap())));
+    let shutdown = Shutdown::new();
+
+    let (reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(health::report_grpc_health::<ProbedService>(
+        reporter,
+        service.clone(),
+        shutdown.clone(),
+        Duration::from_millis(50),
+    ));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(health_service)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    let channel = tonic::transport::Endpoint::from_shared(endpoint).unwrap().connect().await.unwrap();
+    let mut client = HealthClient::new(channel);
+
+    // No configuration loaded yet
+    wait_for_grpc_health(&mut client, ServingStatus::NotServing).await;
+
+    let yaml = r#"
+domain: probed
+descriptors:
+  - key: user
+    rate_limit:
+      requests_per_unit: 1
+      unit: hour
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    service.add_config(CompiledRateLimitConfig::compile(config).unwrap()).await.unwrap();
+    wait_for_grpc_health(&mut client, ServingStatus::Serving).await;
+    assert_eq!(grpc_health_status(&mut client, "").await, ServingStatus::Serving);
+
+    shutdown.begin();
+    wait_for_grpc_health(&mut client, ServingStatus::NotServing).await;
+    assert_eq!(grpc_health_status(&mut client, "").await, ServingStatus::NotServing);
+}
+
+#[tokio::test]
+async fn test_grpc_reflection_descriptor_set() {
+    use prost::Message;
+    use tonic_reflection::pb::{
+        server_reflection_client::ServerReflectionClient,
+        server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+        ServerReflectionRequest,
+    };
+
+    let reflection_service = rust_ratelimit::proto::reflection_service().unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(reflection_service)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let channel = tonic::transport::Endpoint::from_shared(endpoint).unwrap().connect().await.unwrap();
+    let mut client = ServerReflectionClient::new(channel);
+    let requests = [
+        MessageRequest::ListServices(String::new()),
+        MessageRequest::FileContainingSymbol("envoy.service.ratelimit.v3.RateLimitService".to_string()),
+        MessageRequest::FileContainingSymbol("grpc.health.v1.Health".to_string()),
+    ]
+    .map(|request| ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(request),
+    });
+    let mut responses = client
+        .server_reflection_info(tokio_stream::iter(requests))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let Some(MessageResponse::ListServicesResponse(list)) =
+        responses.message().await.unwrap().unwrap().message_response
+    else {
+        panic!("expected a service list");
+    };
+    let services: Vec<_> = list.service.into_iter().map(|service| service.name).collect();
+    assert!(services.contains(&"envoy.service.ratelimit.v3.RateLimitService".to_string()));
+
+    let Some(MessageResponse::FileDescriptorResponse(files)) =
+        responses.message().await.unwrap().unwrap().message_response
+    else {
+        panic!("expected file descriptors");
+    };
+    let files: Vec<_> = files
+        .file_descriptor_proto
+        .iter()
+        .map(|bytes| prost_types::FileDescriptorProto::decode(bytes.as_slice()).unwrap())
+        .collect();
+    let ratelimit = files
+        .iter()
+        .find(|file| file.package() == "envoy.service.ratelimit.v3")
+        .unwrap();
+    assert_eq!(ratelimit.service[0].method[0].name(), "ShouldRateLimit");
+
+    // The health service resolves without local proto files too
+    let Some(MessageResponse::FileDescriptorResponse(files)) =
+        responses.message().await.unwrap().unwrap().message_response
+    else {
+        panic!("expected the health service's file descriptor");
+    };
+    let health = prost_types::FileDescriptorProto::decode(files.file_descriptor_proto[0].as_slice()).unwrap();
+    assert_eq!(health.package(), "grpc.health.v1");
+}
+
+async fn http_request(
+    router: axum::Router,
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<serde_json::Value>,
+) -> (axum::http::StatusCode, serde_json::Value) {
+    use tower_util::ServiceExt;
+
+    let mut request = axum::http::Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(body.to_string())),
+        None => request.body(axum::body::Body::empty()),
+    }
+    .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+}
+
+async fn http_get(router: axum::Router, uri: &str) -> (axum::http::StatusCode, serde_json::Value) {
+    http_request(router, "GET", uri, None, None).await
+}
+
+#[tokio::test]
+async fn test_admin_config_endpoints() {
+    use axum::http::StatusCode;
+    use rust_ratelimit::{admin, metrics::Metrics, service::RateLimitService, utils::MockTimeSource};
+    use std::sync::Arc;
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let service = Arc::new(RateLimitService::new(limiter, Arc::new(Metrics::new().unwrap())));
+    let yaml = r#"
+domain: admin
+descriptors:
+  - key: path
+    value_prefix: /api/
+    rate_limit: { requests_per_unit: 10, unit: minute, name: api }
+    descriptors:
+      - key: method
+        value: POST
+        shadow_mode: true
+        rate_limit: { requests_per_unit: 1, unit: day, timezone: Europe/Paris }
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    service.add_config(CompiledRateLimitConfig::compile(config).unwrap()).await.unwrap();
+    let router = admin::router(service, &admin::AdminConfig::default());
+
+    let (status, dump) = http_get(router.clone(), "/rlconfig").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(dump[0]["domain"], "admin");
+    let rules = dump[0]["rules"].as_array().unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[0]["path"], "path_/api/*");
+    assert_eq!(rules[0]["name"], "api");
+    assert_eq!(rules[0]["unit"], "minute");
+    assert_eq!(rules[1]["path"], "path_/api/*:method_POST");
+    assert_eq!(rules[1]["shadow_mode"], true);
+    assert_eq!(rules[1]["unlimited"], false);
+    assert_eq!(rules[1]["timezone"], "Europe/Paris");
+
+    let (status, lookup) =
+        http_get(router.clone(), "/rlconfig/lookup?domain=admin&entry=path=/api/a,b&entry=method=POST").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(lookup["entries"][0]["value"], "/api/a,b");
+    assert_eq!(lookup["entries"][1]["key"], "method");
+    assert_eq!(lookup["rules"][0]["path"], "path_/api/*:method_POST");
+
+    let (_, lookup) = http_get(router.clone(), "/rlconfig/lookup?domain=admin&entry=path=/web").await;
+    assert!(lookup["rules"].as_array().unwrap().is_empty());
+
+    let (status, _) = http_get(router.clone(), "/rlconfig/lookup?domain=missing&entry=path=/api/").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, error) = http_get(router, "/rlconfig/lookup?domain=admin&entry=path").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["error"], "entry 'path' must be key=value");
+}
+
+#[tokio::test]
+async fn test_admin_counter_endpoints() {
+    use axum::http::StatusCode;
+    use rust_ratelimit::{
+        admin::{self, AdminConfig},
+        metrics::Metrics,
+        service::{GrpcRateLimitDescriptor, GrpcRateLimitDescriptorEntry, GrpcRateLimitRequest, RateLimitService},
+        utils::MockTimeSource,
+    };
+    use serde_json::json;
+    use std::sync::Arc;
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let service = Arc::new(RateLimitService::new(limiter, Arc::new(Metrics::new().unwrap())));
+    let yaml = r#"
+domain: counters
+descriptors:
+  - key: customer
+    rate_limits:
+      - { requests_per_unit: 100, unit: second }
+      - { requests_per_unit: 2, unit: hour, name: hourly }
+"#;
+    let config = rust_ratelimit::config::load_config_from_yaml(yaml).unwrap();
+    service.add_config(CompiledRateLimitConfig::compile(config).unwrap()).await.unwrap();
+
+    let request = GrpcRateLimitRequest {
+        domain: "counters".to_string(),
+        descriptors: vec![GrpcRateLimitDescriptor {
+            entries: vec![GrpcRateLimitDescriptorEntry {
+                key: "customer".to_string(),
+                value: "acme".to_string(),
+            }],
+        }],
+        hits_addend: 1,
+    };
+    for _ in 0..3 {
+        service.should_rate_limit_direct(request.clone()).await.unwrap();
+    }
+
+    // Counter endpoints are only served with a token, and require it
+    let uri = "/admin/counters?domain=counters&entry=customer=acme";
+    let (status, _) = http_get(admin::router(service.clone(), &AdminConfig::default()), uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let router = admin::router(
+        service.clone(),
+        &AdminConfig {
+            token: Some("s3cret".to_string()),
+            ..Default::default()
+        },
+    );
+    let (status, _) = http_request(router.clone(), "GET", uri, None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = http_request(router.clone(), "GET", uri, Some("wrong"), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, view) = http_request(router.clone(), "GET", uri, Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let counters = view["counters"].as_array().unwrap();
+    assert_eq!(counters.len(), 2);
+    assert_eq!(counters[0]["rule"]["unit"], "second");
+    assert_eq!(counters[1]["rule"]["name"], "hourly");
+    assert_eq!(counters[1]["count"], 3);
+    assert!(counters[1]["ttl_secs"].as_u64().unwrap() > 0);
+    assert_eq!(counters[1]["local_over_limit"], true);
+    assert!(counters[1]["key"].as_str().unwrap().contains("counters:customer_acme"));
+
+    // Resetting unblocks the customer immediately
+    let (status, view) = http_request(router.clone(), "DELETE", uri, Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(view["counters"][1]["count"], 0);
+    assert!(view["counters"][1].get("ttl_secs").is_none());
+    assert_eq!(view["counters"][1]["local_over_limit"], false);
+    let response = service.should_rate_limit_direct(request.clone()).await.unwrap();
+    assert!(!response.is_over_limit());
+
+    // Setting the counter to the limit blocks the next request
+    let (status, view) =
+        http_request(router.clone(), "PUT", uri, Some("s3cret"), Some(json!({ "count": 2 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(view["counters"][1]["count"], 2);
+    let response = service.should_rate_limit_direct(request).await.unwrap();
+    assert!(response.is_over_limit());
+
+    // A value containing a comma addresses its own descriptor and leaves acme untouched
+    let comma_request = GrpcRateLimitRequest {
+        domain: "counters".to_string(),
+        descriptors: vec![GrpcRateLimitDescriptor {
+            entries: vec![GrpcRateLimitDescriptorEntry {
+                key: "customer".to_string(),
+                value: "acme,eu".to_string(),
+            }],
+        }],
+        hits_addend: 3,
+    };
+    let response = service.should_rate_limit_direct(comma_request.clone()).await.unwrap();
+    assert!(response.is_over_limit());
+    let comma_uri = "/admin/counters?domain=counters&entry=customer=acme,eu";
+    let (status, view) = http_request(router.clone(), "GET", comma_uri, Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(view["entries"][0]["value"], "acme,eu");
+    assert_eq!(view["counters"][1]["count"], 3);
+    let (status, view) = http_request(router.clone(), "DELETE", comma_uri, Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(view["counters"][1]["count"], 0);
+    let comma_request = GrpcRateLimitRequest {
+        hits_addend: 1,
+        ..comma_request
+    };
+    let response = service.should_rate_limit_direct(comma_request).await.unwrap();
+    assert!(!response.is_over_limit());
+    let (_, view) = http_request(router.clone(), "GET", uri, Some("s3cret"), None).await;
+    assert_eq!(view["counters"][1]["count"], 3);
+
+    let (status, _) = http_request(
+        router,
+        "GET",
+        "/admin/counters?domain=counters&entry=other=x",
+        Some("s3cret"),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_admin_config_crud() {
+    use axum::http::StatusCode;
+    use rust_ratelimit::{
+        admin::{self, AdminConfig},
+        audit::{AuditConfig, AuditLog},
+        metrics::Metrics,
+        service::RateLimitService,
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+    use tower_util::ServiceExt;
+
+    let dir = std::env::temp_dir().join(format!("ratelimit_admin_configs_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let audit_path = dir.join("audit").join("audit.log");
+    let config_dir = dir.join("configs");
+
+    let audit = AuditLog::new(AuditConfig {
+        path: Some(audit_path.clone()),
+        ..Default::default()
+    })
+    .unwrap();
+    let audit = Arc::new(audit);
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000)))
+        .await
+        .with_audit_log(audit.clone());
+    let service = Arc::new(RateLimitService::new(limiter, Arc::new(Metrics::new().unwrap())));
+    let router = admin::router(
+        service.clone(),
+        &AdminConfig {
+            token: Some("s3cret".to_string()),
+            config_dir: Some(config_dir.clone()),
+        },
+    );
+
+    let put = |uri: &'static str, content_type: &'static str, body: &'static str| {
+        let router = router.clone();
+        async move {
+            let request = axum::http::Request::put(uri)
+                .header("authorization", "Bearer s3cret")
+                .header(admin::ACTOR_HEADER, "alice")
+                .header("content-type", content_type)
+                .body(axum::body::Body::from(body))
+                .unwrap();
+            let response = router.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+        }
+    };
+
+    // Create from YAML; the file is persisted and the config is live
+    let yaml = "domain: uploads\ndescriptors:\n  - key: user\n    rate_limit: { requests_per_unit: 10, unit: minute }\n";
+    let (status, created) = put("/admin/configs/uploads", "application/yaml", yaml).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["descriptors"][0]["rate_limit"]["requests_per_unit"], 10);
+    let persisted = std::fs::read_to_string(config_dir.join("uploads.yaml")).unwrap();
+    let reloaded = rust_ratelimit::config::load_config_from_yaml(&persisted).unwrap();
+    assert_eq!(reloaded.descriptors[0].rate_limit.as_ref().unwrap().requests_per_unit, 10);
+    assert!(!persisted.contains("null"));
+    assert!(service.limiter().await.get_config("uploads").is_some());
+
+    // Replace from JSON
+    let json = r#"{"domain":"uploads","descriptors":[{"key":"user","rate_limit":{"requests_per_unit":20,"unit":"minute"}}]}"#;
+    let (status, _) = put("/admin/configs/uploads", "application/json", json).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, listed) = http_request(router.clone(), "GET", "/admin/configs", Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed[0]["domain"], "uploads");
+    assert_eq!(listed[0]["descriptors"][0]["rate_limit"]["requests_per_unit"], 20);
+
+    // Invalid configs are rejected without touching the loaded one
+    let duplicate_windows = "domain: uploads\ndescriptors:\n  - key: user\n    rate_limits:\n      - { requests_per_unit: 1, unit: minute }\n      - { requests_per_unit: 2, unit: minute }\n";
+    let (status, error) = put("/admin/configs/uploads", "application/yaml", duplicate_windows).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error["error"].as_str().unwrap().contains("duplicate minute window"));
+    let (status, _) = put("/admin/configs/other", "application/yaml", yaml).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = put("/admin/configs/uploads", "application/json", "{").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, current) = http_request(router.clone(), "GET", "/admin/configs/uploads", Some("s3cret"), None).await;
+    assert_eq!(current["descriptors"][0]["rate_limit"]["requests_per_unit"], 20);
+
+    // Config management requires the token
+    let (status, _) = http_request(router.clone(), "DELETE", "/admin/configs/uploads", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = http_request(router.clone(), "DELETE", "/admin/configs/uploads", Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(!config_dir.join("uploads.yaml").exists());
+    assert!(service.limiter().await.get_config("uploads").is_none());
+    let (status, _) = http_request(router.clone(), "DELETE", "/admin/configs/uploads", Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    audit.flush();
+    let events: Vec<serde_json::Value> = std::fs::read_to_string(&audit_path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let actions: Vec<_> = events.iter().map(|event| event["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, ["create", "replace", "delete"]);
+    assert!(events.iter().all(|event| event["persisted"] == true));
+    assert_eq!(events[0]["actor"], "alice");
+    // No actor header falls back to the default
+    assert_eq!(events[2]["actor"], "admin");
+    assert_eq!(events[1]["config"]["descriptors"][0]["rate_limit"]["requests_per_unit"], 20);
+    assert!(events[2].get("config").is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_admin_config_rolled_back_when_not_persisted() {
+    use axum::http::StatusCode;
+    use rust_ratelimit::{
+        admin::{self, AdminConfig},
+        metrics::Metrics,
+        service::RateLimitService,
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+
+    // A regular file where the config directory should be makes every write fail
+    let blocked = std::env::temp_dir().join(format!("ratelimit_admin_blocked_{}", std::process::id()));
+    std::fs::write(&blocked, "not a directory").unwrap();
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let service = Arc::new(RateLimitService::new(limiter, Arc::new(Metrics::new().unwrap())));
+    let router = admin::router(
+        service.clone(),
+        &AdminConfig {
+            token: Some("s3cret".to_string()),
+            config_dir: Some(blocked.clone()),
+        },
+    );
+
+    let config = serde_json::json!({
+        "domain": "uploads",
+        "descriptors": [{"key": "user", "rate_limit": {"requests_per_unit": 20, "unit": "minute"}}],
+    });
+    let (status, error) =
+        http_request(router.clone(), "PUT", "/admin/configs/uploads", Some("s3cret"), Some(config)).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(error["error"].as_str().unwrap().contains("failed to persist"));
+    assert!(service.limiter().await.get_config("uploads").is_none());
+
+    let _ = std::fs::remove_file(&blocked);
+}
+
+#[tokio::test]
+async fn test_admin_persists_to_the_domain_file() {
+    use axum::http::StatusCode;
+    use rust_ratelimit::{
+        admin::{self, AdminConfig},
+        config::load_config_from_file,
+        metrics::Metrics,
+        service::RateLimitService,
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("ratelimit_admin_files_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = |domain: &str, requests_per_unit: u32| {
+        serde_json::json!({
+            "domain": domain,
+            "descriptors": [{"key": "user", "rate_limit": {"requests_per_unit": requests_per_unit, "unit": "minute"}}],
+        })
+    };
+    let write = |file: &str, value: &serde_json::Value| {
+        let config: RateLimitConfig = serde_json::from_value(value.clone()).unwrap();
+        let content = match file.rsplit('.').next() {
+            Some("json") => serde_json::to_string(&config).unwrap(),
+            Some("toml") => toml::to_string(&config).unwrap(),
+            _ => serde_yaml::to_string(&config).unwrap(),
+        };
+        std::fs::write(dir.join(file), content).unwrap();
+    };
+    let requests_per_unit = |file: &str| {
+        let config = load_config_from_file(dir.join(file)).unwrap();
+        config.descriptors[0].rate_limit.as_ref().unwrap().requests_per_unit
+    };
+    write("uploads.json", &config("uploads", 1));
+    write("team-a.toml", &config("search", 1));
+    write("twice-1.yaml", &config("twice", 1));
+    write("twice-2.yml", &config("twice", 1));
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let service = Arc::new(RateLimitService::new(limiter, Arc::new(Metrics::new().unwrap())));
+    let router = admin::router(
+        service.clone(),
+        &AdminConfig {
+            token: Some("s3cret".to_string()),
+            config_dir: Some(dir.clone()),
+        },
+    );
+    let put = |domain: &'static str, requests_per_unit: u32| {
+        let router = router.clone();
+        async move {
+            let uri = format!("/admin/configs/{}", domain);
+            http_request(router, "PUT", &uri, Some("s3cret"), Some(config(domain, requests_per_unit))).await
+        }
+    };
+
+    // Files are rewritten in place, keeping their name and format
+    let (status, _) = put("uploads", 2).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(requests_per_unit("uploads.json"), 2);
+    assert!(!dir.join("uploads.yaml").exists());
+    let (status, _) = put("search", 3).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(requests_per_unit("team-a.toml"), 3);
+    assert!(!dir.join("search.yaml").exists());
+
+    // A domain in several files, or a new file name holding another domain, would not load
+    let (status, error) = put("twice", 2).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(error["error"].as_str().unwrap().contains("several files"));
+    assert!(service.limiter().await.get_config("twice").is_none());
+    write("billing.yaml", &config("payments", 1));
+    let (status, error) = put("billing", 1).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(error["error"].as_str().unwrap().contains("holds another domain"));
+
+    // Removing a domain removes its file
+    let (status, _) = http_request(router.clone(), "DELETE", "/admin/configs/search", Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(!dir.join("team-a.toml").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_admin_refuses_provider_domains() {
+    use axum::http::StatusCode;
+    use rust_ratelimit::{
+        admin::{Admin, AdminConfig},
+        metrics::Metrics,
+        provider::{compile_snapshot, ConfigSources},
+        service::RateLimitService,
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("ratelimit_admin_owned_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let service = Arc::new(RateLimitService::new(limiter, metrics.clone()));
+    let sources = ConfigSources::new(service.clone(), metrics);
+    let config = |domain: &str| {
+        rust_ratelimit::config::load_config_from_yaml(&format!(
+            "domain: {}\ndescriptors:\n  - key: user\n    rate_limit: {{ requests_per_unit: 1, unit: second }}\n",
+            domain
+        ))
+        .unwrap()
+    };
+    let mut xds = sources.register("xDS server http://xds:18000", None);
+    xds.apply(compile_snapshot(vec![config("central")]).unwrap()).await.unwrap();
+    let mut files = sources.register(dir.display().to_string(), Some(dir.clone()));
+    files.apply(compile_snapshot(vec![config("local")]).unwrap()).await.unwrap();
+
+    let admin = Admin::new(
+        service.clone(),
+        &AdminConfig {
+            token: Some("s3cret".to_string()),
+            config_dir: Some(dir.clone()),
+        },
+    );
+    let router = Arc::new(admin.with_config_sources(sources)).router();
+    let body = |domain: &str| Some(serde_json::to_value(config(domain)).unwrap());
+
+    // The management server would undo changes to its domains
+    let (status, error) =
+        http_request(router.clone(), "PUT", "/admin/configs/central", Some("s3cret"), body("central")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(error["error"].as_str().unwrap().contains("provided by xDS server http://xds:18000"));
+    let (status, _) = http_request(router.clone(), "DELETE", "/admin/configs/central", Some("s3cret"), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(service.limiter().await.get_config("central").is_some());
+
+    // Domains of the watched directory the admin API persists to can be changed
+    let (status, _) = http_request(router.clone(), "PUT", "/admin/configs/local", Some("s3cret"), body("local")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = http_request(router.clone(), "PUT", "/admin/configs/new", Some("s3cret"), body("new")).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_grpc_config_admin() {
+    use rust_ratelimit::{
+        admin::{Admin, AdminConfig, ACTOR_HEADER},
+        metrics::Metrics,
+        proto::generated::ratelimit::admin::v3::{
+            config_admin_service_client::ConfigAdminServiceClient, Config, ConfigFormat,
+            DeleteConfigRequest, GetConfigRequest, PutConfigRequest,
+        },
+        service::RateLimitService,
+        utils::MockTimeSource,
+    };
+    use std::sync::Arc;
+
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let service = Arc::new(RateLimitService::new(limiter, Arc::new(Metrics::new().unwrap())));
+    let admin = Arc::new(Admin::new(
+        service.clone(),
+        &AdminConfig {
+            token: Some("s3cret".to_string()),
+            config_dir: None,
+        },
+    ));
+    // No gRPC admin service without a token
+    assert!(Arc::new(Admin::new(service.clone(), &AdminConfig::default())).grpc_service().is_none());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_optional_service(admin.grpc_service())
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    let channel = tonic::transport::Endpoint::from_shared(endpoint).unwrap().connect().await.unwrap();
+    let mut client = ConfigAdminServiceClient::new(channel);
+    fn request<T>(message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        request.metadata_mut().insert("authorization", "Bearer s3cret".parse().unwrap());
+        request.metadata_mut().insert(ACTOR_HEADER, "bob".parse().unwrap());
+        request
+    }
+
+    let toml = "domain = \"grpc_admin\"\n\n[[descriptors]]\nkey = \"user\"\nrate_limit = { requests_per_unit = 5, unit = \"minute\" }\n";
+    let put = PutConfigRequest {
+        config: Some(Config {
+            domain: "grpc_admin".to_string(),
+            format: ConfigFormat::Toml.into(),
+            content: toml.to_string(),
+        }),
+    };
+
+    // Calls without the token are rejected
+    let status = client.put_config(put.clone()).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+    let response = client.put_config(request(put.clone())).await.unwrap().into_inner();
+    assert!(response.created);
+    assert!(!response.persisted);
+    assert!(service.limiter().await.get_config("grpc_admin").is_some());
+    let response = client.put_config(request(put)).await.unwrap().into_inner();
+    assert!(!response.created);
+
+    let config = client
+        .get_config(request(GetConfigRequest {
+            domain: "grpc_admin".to_string(),
+            format: ConfigFormat::Json.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let json: serde_json::Value = serde_json::from_str(&config.content).unwrap();
+    assert_eq!(json["descriptors"][0]["rate_limit"]["requests_per_unit"], 5);
+
+    // Invalid configs are rejected
+    let status = client
+        .put_config(request(PutConfigRequest {
+            config: Some(Config {
+                domain: "grpc_admin".to_string(),
+                format: ConfigFormat::Yaml.into(),
+                content: "domain: other\ndescriptors: []\n".to_string(),
+            }),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    client
+        .delete_config(request(DeleteConfigRequest { domain: "grpc_admin".to_string() }))
+        .await
+        .unwrap();
+    assert!(service.limiter().await.get_config("grpc_admin").is_none());
+    let status = client
+        .delete_config(request(DeleteConfigRequest { domain: "grpc_admin".to_string() }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
+type AdsResponses = tokio::sync::mpsc::Receiver<Result<DiscoveryResponse, tonic::Status>>;
+
+/// In-process stand-in for an xDS management server. Each stream serves the next
+/// queued response channel; every request received is forwarded to the test.
+#[derive(Clone)]
+struct StandInAds {
+    streams: std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<AdsResponses>>>,
+    requests: tokio::sync::mpsc::UnboundedSender<DiscoveryRequest>,
+}
+
+#[tonic::async_trait]
+impl AggregatedDiscoveryService for StandInAds {
+    type StreamAggregatedResourcesStream = tokio_stream::wrappers::ReceiverStream<Result<DiscoveryResponse, tonic::Status>>;
+
+    async fn stream_aggregated_resources(
+        &self,
+        request: tonic::Request<tonic::Streaming<DiscoveryRequest>>,
+    ) -> Result<tonic::Response<Self::StreamAggregatedResourcesStream>, tonic::Status> {
+        let responses = self
+            .streams
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| tonic::Status::unavailable("no stream queued"))?;
+        let mut incoming = request.into_inner();
+        let requests = self.requests.clone();
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = incoming.message().await {
+                let _ = requests.send(request);
+            }
+        });
+        Ok(tonic::Response::new(tokio_stream::wrappers::ReceiverStream::new(responses)))
+    }
+}
+
+async fn next_xds_request(requests: &mut tokio::sync::mpsc::UnboundedReceiver<DiscoveryRequest>) -> DiscoveryRequest {
+    tokio::time::timeout(std::time::Duration::from_secs(5), requests.recv())
+        .await
+        .expect("timed out waiting for a request")
+        .unwrap()
+}
+
+fn xds_response(version: &str, configs: &[rust_ratelimit::proto::generated::ratelimit::config::v3::RateLimitConfig]) -> DiscoveryResponse {
+    use prost::Message;
+    use rust_ratelimit::xds::RATE_LIMIT_CONFIG_TYPE_URL;
+
+    DiscoveryResponse {
+        version_info: version.to_string(),
+        resources: configs
+            .iter()
+            .map(|config| prost_types::Any {
+                type_url: RATE_LIMIT_CONFIG_TYPE_URL.to_string(),
+                value: config.encode_to_vec(),
+            })
+            .collect(),
+        canary: false,
+        type_url: RATE_LIMIT_CONFIG_TYPE_URL.to_string(),
+        nonce: format!("nonce-{}", version),
+    }
+}
+
+fn xds_config(domain: &str, unit: i32) -> rust_ratelimit::proto::generated::ratelimit::config::v3::RateLimitConfig {
+    use rust_ratelimit::proto::generated::ratelimit::config::v3 as pb;
+
+    pb::RateLimitConfig {
+        name: domain.to_string(),
+        domain: domain.to_string(),
+        descriptors: vec![pb::RateLimitDescriptor {
+            key: "user".to_string(),
+            value: String::new(),
+            rate_limit: Some(pb::RateLimit {
+                requests_per_unit: 2,
+                unit,
+                unlimited: false,
+            }),
+            shadow_mode: false,
+            descriptors: Vec::new(),
+        }],
+    }
+}
+
+#[tokio::test]
+async fn test_xds_config_provider() {
+    use rust_ratelimit::{
+        config::load_config_from_yaml,
+        metrics::Metrics,
+        proto::generated::{
+            envoy::service::discovery::v3::aggregated_discovery_service_server::AggregatedDiscoveryServiceServer,
+            ratelimit::config::v3::rate_limit::Unit,
+        },
+        service::RateLimitService,
+        shutdown::Shutdown,
+        utils::MockTimeSource,
+        provider::{ConfigApplier, ConfigProvider},
+        xds::{XdsConfig, XdsConfigProvider, RATE_LIMIT_CONFIG_TYPE_URL},
+    };
+    use std::{sync::Arc, time::Duration};
+    use tokio::sync::mpsc;
+
+    let (requests_tx, mut requests) = mpsc::unbounded_channel();
+    let (responses, first_stream) = mpsc::channel(4);
+    let ads = StandInAds {
+        streams: Arc::new(std::sync::Mutex::new([first_stream].into())),
+        requests: requests_tx,
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(AggregatedDiscoveryServiceServer::new(ads.clone()))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let limiter = create_redis_limiter(Arc::new(MockTimeSource::from_unix(1_700_000_000))).await;
+    let service = Arc::new(RateLimitService::new(limiter, metrics.clone()));
+    // Loaded from a file; never sent over xDS, so never removed by it
+    let local = load_config_from_yaml("domain: local\ndescriptors:\n  - key: user\n").unwrap();
+    service.add_config(CompiledRateLimitConfig::compile(local).unwrap()).await.unwrap();
+
+    let config = XdsConfig {
+        node_id: "ratelimit-test".to_string(),
+        retry_interval: Duration::from_millis(50),
+        // No scheme, as the Go service accepts
+        ..XdsConfig::new(address)
+    };
+    let shutdown = Shutdown::new();
+    let snapshots = Box::new(XdsConfigProvider::new(config)).snapshots();
+    let applier = tokio::spawn(ConfigApplier::new("xDS", service.clone(), metrics).run(snapshots, shutdown.clone()));
+
+    // Snapshots are applied after the ACK is sent
+    let wait_for_domains = |expected: &'static [&'static str]| {
+        let service = service.clone();
+        async move {
+            for _ in 0..100 {
+                let limiter = service.limiter().await;
+                let domains: Vec<_> = limiter.configs().iter().map(|config| config.domain()).collect();
+                if domains == expected {
+                    return;
+                }
+                drop(limiter);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("domains never became {:?}", expected);
+        }
+    };
+
+    // Subscription: wildcard request for the rate limit config type
+    let subscribe = next_xds_request(&mut requests).await;
+    assert_eq!(subscribe.node.as_ref().unwrap().id, "ratelimit-test");
+    assert_eq!(subscribe.type_url, RATE_LIMIT_CONFIG_TYPE_URL);
+    assert!(subscribe.resource_names.is_empty());
+    assert_eq!(subscribe.version_info, "");
+    assert_eq!(subscribe.response_nonce, "");
+
+    // Valid config is applied and ACKed
+    let minute = Unit::Minute as i32;
+    responses.send(Ok(xds_response("1", &[xds_config("api", minute), xds_config("web", minute)]))).await.unwrap();
+    let ack = next_xds_request(&mut requests).await;
+    assert_eq!((ack.version_info.as_str(), ack.response_nonce.as_str()), ("1", "nonce-1"));
+    assert!(ack.error_detail.is_none());
+    wait_for_domains(&["api", "local", "web"]).await;
+
+    // An invalid resource rejects the whole response and keeps the last version
+    responses
+        .send(Ok(xds_response("2", &[xds_config("api", Unit::Hour as i32), xds_config("web", Unit::Unknown as i32)])))
+        .await
+        .unwrap();
+    let nack = next_xds_request(&mut requests).await;
+    assert_eq!((nack.version_info.as_str(), nack.response_nonce.as_str()), ("1", "nonce-2"));
+    let error = nack.error_detail.unwrap();
+    assert_eq!(error.code, tonic::Code::InvalidArgument as i32);
+    assert!(error.message.contains("unknown unit"), "{}", error.message);
+    let limiter = service.limiter().await;
+    assert!(matches!(
+        limiter.get_config("api").unwrap().source().descriptors[0].rate_limit.as_ref().unwrap().unit,
+        rust_ratelimit::config::RateLimitUnit::Minute
+    ));
+    drop(limiter);
+
+    // Domains left out of a response are removed
+    responses.send(Ok(xds_response("3", &[xds_config("api", Unit::Hour as i32)]))).await.unwrap();
+    let ack = next_xds_request(&mut requests).await;
+    assert_eq!(ack.version_info, "3");
+    assert!(ack.error_detail.is_none());
+    wait_for_domains(&["api", "local"]).await;
+
+    // After the stream closes the provider reconnects and resumes from the accepted version
+    let (_second_responses, second_stream) = mpsc::channel(4);
+    ads.streams.lock().unwrap().push_back(second_stream);
+    drop(responses);
+    let resubscribe = next_xds_request(&mut requests).await;
+    assert_eq!(resubscribe.version_info, "3");
+    assert_eq!(resubscribe.response_nonce, "");
+
+    shutdown.begin();
+    tokio::time::timeout(Duration::from_secs(5), applier).await.unwrap().unwrap();
 }
-*/
\ No newline at end of file